@@ -0,0 +1,179 @@
+// Builds `Packet` trees from a small expression language, so test
+// transmissions can be written by hand and encoded to hex:
+//
+//     sum:v3(1, product:v1:count(2, 3), gt(4:v5, 5))
+//
+// Operators are called by name (sum, product, min, max, gt, lt, eq) and
+// literals are decimal. Either may be followed by `:vN` to set the version,
+// and an operator by `:bits` or `:count` to pick length type 0 or 1. The
+// defaults are version 0 and `bits`.
+
+use nom::branch::alt;
+use nom::bytes::complete::tag;
+use nom::character::complete::{alpha1, char, digit1, multispace0};
+use nom::combinator::{all_consuming, cut, map, map_res, verify};
+use nom::error::Error;
+use nom::multi::many0;
+use nom::sequence::{delimited, pair, preceded, terminated, tuple};
+use nom::{Finish, IResult};
+
+use crate::encode::{min_groups, place};
+use crate::{Literal, Operator, OperatorType, Packet};
+
+const OPERATORS: [OperatorType; 7] = [
+    OperatorType::Sum,
+    OperatorType::Product,
+    OperatorType::Minimum,
+    OperatorType::Maximum,
+    OperatorType::GreaterThan,
+    OperatorType::LessThan,
+    OperatorType::EqualTo,
+];
+
+enum Annotation {
+    Version(u8),
+    LengthType(u8),
+}
+
+// The packet is placed as if it starts its own transmission.
+pub fn assemble(source: &str) -> Result<Packet, String> {
+    match all_consuming(delimited(multispace0, expr, multispace0))(source).finish() {
+        Ok((_, mut packet)) => {
+            place(&mut packet, 0);
+            Ok(packet)
+        }
+        Err(Error { input, .. }) => {
+            let column = source.len() - input.len() + 1;
+            match input.chars().next() {
+                Some(c) => Err(format!("unexpected {:?} at column {}", c, column)),
+                None => Err(format!("unexpected end of input at column {}", column)),
+            }
+        }
+    }
+}
+
+fn expr(input: &str) -> IResult<&str, Packet> {
+    alt((operator, literal))(input)
+}
+
+fn version(input: &str) -> IResult<&str, u8> {
+    preceded(
+        char('v'),
+        verify(map_res(digit1, str::parse::<u8>), |&v| v <= 0x07),
+    )(input)
+}
+
+fn annotations(input: &str) -> IResult<&str, Vec<Annotation>> {
+    many0(preceded(
+        char(':'),
+        alt((
+            map(version, Annotation::Version),
+            map(tag("bits"), |_| Annotation::LengthType(0)),
+            map(tag("count"), |_| Annotation::LengthType(1)),
+        )),
+    ))(input)
+}
+
+fn literal(input: &str) -> IResult<&str, Packet> {
+    // Literals have no length type, so only a version may follow them.
+    map(
+        tuple((
+            map_res(digit1, str::parse::<u64>),
+            many0(preceded(char(':'), version)),
+        )),
+        |(value, versions)| {
+            Packet::Literal(Literal {
+                version: versions.last().copied().unwrap_or(0),
+                value,
+                groups: min_groups(value),
+                offset: 0,
+            })
+        },
+    )(input)
+}
+
+fn operator_type(input: &str) -> IResult<&str, OperatorType> {
+    map_res(alpha1, |name: &str| {
+        OPERATORS.into_iter().find(|op| op.name() == name).ok_or(())
+    })(input)
+}
+
+// Once an operator's name has been read the rest must parse, so errors are
+// reported where they happen rather than at the start of the expression.
+fn operands(input: &str) -> IResult<&str, Vec<Packet>> {
+    let separator = tuple((multispace0, char(','), multispace0));
+    let list = map(
+        pair(expr, many0(preceded(separator, cut(expr)))),
+        |(first, mut rest)| {
+            rest.insert(0, first);
+            rest
+        },
+    );
+    preceded(
+        tuple((multispace0, char('('), multispace0)),
+        alt((
+            map(char(')'), |_| vec![]),
+            terminated(list, pair(multispace0, char(')'))),
+        )),
+    )(input)
+}
+
+fn operator(input: &str) -> IResult<&str, Packet> {
+    map(
+        pair(operator_type, cut(pair(annotations, operands))),
+        |(operator, (notes, subpackets))| {
+            let mut version = 0;
+            let mut length_type = 0;
+            for note in notes {
+                match note {
+                    Annotation::Version(v) => version = v,
+                    Annotation::LengthType(t) => length_type = t,
+                }
+            }
+            Packet::Operator(Operator {
+                version,
+                operator,
+                length_type,
+                subpackets,
+                offset: 0,
+            })
+        },
+    )(input)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::encode::{encode, to_hex};
+
+    // Hand-written programs for the puzzle's examples assemble to their hex.
+    #[test]
+    fn examples_assemble_to_their_hex() {
+        let cases = [
+            ("2021:v6", "D2FE28"),
+            ("lt:v1(10:v6, 20:v2)", "38006F45291200"),
+            ("max:v7:count(1:v2, 2:v4, 3:v1)", "EE00D40C823060"),
+            (
+                "eq:v4 ( sum:v2:count(1:v2, 3:v4), product:v6:count(2, 2:v2) )",
+                "9C0141080250320F1802104A08",
+            ),
+        ];
+        for (source, expected) in cases {
+            let packet = assemble(source)
+                .unwrap_or_else(|e| panic!("{:?} failed to assemble: {}", source, e));
+            let bytes = encode(&packet).unwrap();
+            assert_eq!(to_hex(&bytes), expected, "{:?}", source);
+        }
+    }
+
+    #[test]
+    fn malformed_programs_are_errors() {
+        for source in ["", "sum(1,", "sum(1) 2", "mul(2, 3)", "5:bits", "1:v8"] {
+            assert!(
+                assemble(source).is_err(),
+                "{:?} should not assemble",
+                source
+            );
+        }
+    }
+}
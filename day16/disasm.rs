@@ -0,0 +1,151 @@
+// Renders a `Packet` tree for reading. `sexpr` gives one packet per line,
+// annotated with its version, bit offset in the transmission and how its
+// subpackets are delimited; `infix` gives just the arithmetic on one line.
+
+use std::fmt::Write;
+
+use crate::encode::{bit_len, header_len};
+use crate::{OperatorType, Packet};
+
+impl OperatorType {
+    pub fn symbol(&self) -> &'static str {
+        match self {
+            OperatorType::Sum => "+",
+            OperatorType::Product => "*",
+            OperatorType::Minimum => "min",
+            OperatorType::Maximum => "max",
+            OperatorType::GreaterThan => ">",
+            OperatorType::LessThan => "<",
+            OperatorType::EqualTo => "=",
+        }
+    }
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            OperatorType::Sum => "sum",
+            OperatorType::Product => "product",
+            OperatorType::Minimum => "min",
+            OperatorType::Maximum => "max",
+            OperatorType::GreaterThan => "gt",
+            OperatorType::LessThan => "lt",
+            OperatorType::EqualTo => "eq",
+        }
+    }
+}
+
+// For example:
+//
+// (+ v3@0 count=2
+//   (* v1@18 bits=22
+//     5:v2@40
+//     7:v4@51)
+//   9:v0@62)
+pub fn sexpr(p: &Packet) -> String {
+    let mut out = String::new();
+    write_sexpr(&mut out, p, 0);
+    out
+}
+
+fn write_sexpr(out: &mut String, p: &Packet, depth: usize) {
+    let indent = "  ".repeat(depth);
+    match p {
+        Packet::Literal(lit) => {
+            write!(
+                out,
+                "{}{}:v{}@{}",
+                indent, lit.value, lit.version, lit.offset
+            )
+            .unwrap();
+        }
+        Packet::Operator(op) => {
+            let length = match op.length_type {
+                0 => format!("bits={}", bit_len(p) - header_len(op)),
+                _ => format!("count={}", op.subpackets.len()),
+            };
+            write!(
+                out,
+                "{}({} v{}@{} {}",
+                indent,
+                op.operator.symbol(),
+                op.version,
+                op.offset,
+                length
+            )
+            .unwrap();
+
+            for sub in &op.subpackets {
+                out.push('\n');
+                write_sexpr(out, sub, depth + 1);
+            }
+            out.push(')');
+        }
+    }
+}
+
+// For example `(5 * 7) + min(9)`.
+pub fn infix(p: &Packet) -> String {
+    infix_expr(p, false)
+}
+
+fn infix_expr(p: &Packet, nested: bool) -> String {
+    let op = match p {
+        Packet::Literal(lit) => return lit.value.to_string(),
+        Packet::Operator(op) => op,
+    };
+
+    let binary = match op.operator {
+        OperatorType::Sum | OperatorType::Product => op.subpackets.len() >= 2,
+        OperatorType::GreaterThan | OperatorType::LessThan | OperatorType::EqualTo => {
+            op.subpackets.len() == 2
+        }
+        OperatorType::Minimum | OperatorType::Maximum => false,
+    };
+    if !binary {
+        // Function-call form for min and max, and for operators with an
+        // unusual number of operands.
+        let args: Vec<String> = op.subpackets.iter().map(|s| infix_expr(s, false)).collect();
+        return format!("{}({})", op.operator.name(), args.join(", "));
+    }
+
+    let symbol = match op.operator {
+        OperatorType::EqualTo => "==",
+        ref other => other.symbol(),
+    };
+    let terms: Vec<String> = op.subpackets.iter().map(|s| infix_expr(s, true)).collect();
+    let expr = terms.join(&format!(" {} ", symbol));
+    if nested {
+        format!("({})", expr)
+    } else {
+        expr
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{decode_hex, parse};
+
+    fn disassemble(hex: &str) -> String {
+        let (_, packet) = parse(&decode_hex(hex).unwrap()).unwrap();
+        sexpr(&packet)
+    }
+
+    #[test]
+    fn offsets_are_where_packets_start() {
+        assert_eq!(
+            disassemble("9C0141080250320F1802104A08"),
+            "(= v4@0 bits=80\n  (+ v2@22 count=2\n    1:v2@40\n    3:v4@51)\n  (* v6@62 count=2\n    2:v0@80\n    2:v2@91))"
+        );
+        // The first literal is written with a leading zero group.
+        assert_eq!(
+            disassemble("1600C481440881"),
+            "(> v0@0 count=3\n  5:v0@18\n  1:v0@34\n  1:v0@45)"
+        );
+    }
+
+    #[test]
+    fn infix_forms() {
+        let (_, packet) = parse(&decode_hex("9C0141080250320F1802104A08").unwrap()).unwrap();
+        assert_eq!(infix(&packet), "(1 + 3) == (2 * 2)");
+    }
+}
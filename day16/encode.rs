@@ -0,0 +1,280 @@
+// Serialises a `Packet` tree back into a BITS transmission: the inverse of
+// `parse`. Each operator keeps the length type and each literal the number of
+// groups it was decoded (or built) with, so re-encoding a parsed transmission
+// reproduces it bit for bit, up to the zero padding at the end.
+
+use crate::{Literal, Operator, OperatorType, Packet};
+
+#[derive(Debug, Default)]
+struct BitWriter {
+    bits: Vec<bool>,
+}
+
+impl BitWriter {
+    // Appends the low `width` bits of `value`, most significant first.
+    fn push(&mut self, value: u64, width: usize) {
+        for i in (0..width).rev() {
+            self.bits.push(value >> i & 1 == 1);
+        }
+    }
+
+    fn append(&mut self, other: BitWriter) {
+        self.bits.extend(other.bits);
+    }
+
+    // Packs the bits into bytes, zero-padding the final byte.
+    fn into_bytes(self) -> Vec<u8> {
+        self.bits
+            .chunks(8)
+            .map(|chunk| {
+                chunk
+                    .iter()
+                    .enumerate()
+                    .fold(0, |acc, (i, &bit)| acc | (bit as u8) << (7 - i))
+            })
+            .collect()
+    }
+}
+
+impl OperatorType {
+    pub fn type_id(&self) -> u8 {
+        match self {
+            OperatorType::Sum => 0x00,
+            OperatorType::Product => 0x01,
+            OperatorType::Minimum => 0x02,
+            OperatorType::Maximum => 0x03,
+            OperatorType::GreaterThan => 0x05,
+            OperatorType::LessThan => 0x06,
+            OperatorType::EqualTo => 0x07,
+        }
+    }
+}
+
+pub fn encode(p: &Packet) -> Result<Vec<u8>, String> {
+    let mut out = BitWriter::default();
+    write_packet(&mut out, p)?;
+    Ok(out.into_bytes())
+}
+
+pub fn to_hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|b| format!("{:02X}", b)).collect()
+}
+
+// The number of bits `encode` writes for the packet, before byte padding.
+pub fn bit_len(p: &Packet) -> usize {
+    match p {
+        Packet::Literal(lit) => 6 + 5 * lit.groups,
+        Packet::Operator(op) => header_len(op) + op.subpackets.iter().map(bit_len).sum::<usize>(),
+    }
+}
+
+// Sets the offsets of a packet built by hand to where `encode` would put it
+// and its subpackets, as if it started `offset` bits into a transmission.
+pub fn place(p: &mut Packet, offset: usize) {
+    match p {
+        Packet::Literal(lit) => lit.offset = offset,
+        Packet::Operator(op) => {
+            op.offset = offset;
+            let mut child = offset + header_len(op);
+            for sub in &mut op.subpackets {
+                place(sub, child);
+                child += bit_len(sub);
+            }
+        }
+    }
+}
+
+// Version, type ID, length type and the length or count that follows it.
+pub fn header_len(op: &Operator) -> usize {
+    match op.length_type {
+        0 => 6 + 1 + 15,
+        _ => 6 + 1 + 11,
+    }
+}
+
+// The fewest 4-bit groups that hold the value, and always at least one.
+pub fn min_groups(value: u64) -> usize {
+    (64 - value.leading_zeros() as usize).div_ceil(4).max(1)
+}
+
+fn write_version(out: &mut BitWriter, version: u8) -> Result<(), String> {
+    if version > 0x07 {
+        return Err(format!("version {} does not fit in 3 bits", version));
+    }
+    out.push(version as u64, 3);
+    Ok(())
+}
+
+fn write_packet(out: &mut BitWriter, p: &Packet) -> Result<(), String> {
+    match p {
+        Packet::Literal(lit) => write_literal(out, lit),
+        Packet::Operator(op) => write_operator(out, op),
+    }
+}
+
+fn write_literal(out: &mut BitWriter, lit: &Literal) -> Result<(), String> {
+    write_version(out, lit.version)?;
+    out.push(0x04, 3);
+
+    if lit.groups < min_groups(lit.value) {
+        return Err(format!(
+            "{} does not fit in {} groups",
+            lit.value, lit.groups
+        ));
+    }
+    // Groups beyond the sixteenth are leading zeros.
+    for i in (0..lit.groups).rev() {
+        out.push((i != 0) as u64, 1);
+        out.push(lit.value.checked_shr(i as u32 * 4).unwrap_or(0) & 0x0f, 4);
+    }
+    Ok(())
+}
+
+fn write_operator(out: &mut BitWriter, op: &Operator) -> Result<(), String> {
+    write_version(out, op.version)?;
+    out.push(op.operator.type_id() as u64, 3);
+
+    let mut body = BitWriter::default();
+    for sub in &op.subpackets {
+        write_packet(&mut body, sub)?;
+    }
+
+    match op.length_type {
+        0 => {
+            if body.bits.len() >= 1 << 15 {
+                return Err(format!(
+                    "{} bits of subpackets do not fit in a 15-bit length",
+                    body.bits.len()
+                ));
+            }
+            out.push(0, 1);
+            out.push(body.bits.len() as u64, 15);
+        }
+        1 => {
+            if op.subpackets.len() >= 1 << 11 {
+                return Err(format!(
+                    "{} subpackets do not fit in an 11-bit count",
+                    op.subpackets.len()
+                ));
+            }
+            out.push(1, 1);
+            out.push(op.subpackets.len() as u64, 11);
+        }
+        other => return Err(format!("unknown length type {}", other)),
+    }
+    out.append(body);
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{decode_hex, parse};
+    use rand::rngs::StdRng;
+    use rand::{Rng, SeedableRng};
+
+    fn random_packet(rng: &mut StdRng, depth: u32) -> Packet {
+        let version = rng.gen_range(0..8);
+        if depth == 0 || rng.gen_bool(0.4) {
+            // Mostly small values, with the odd one using every bit.
+            let bits = rng.gen_range(1..40);
+            let value = match rng.gen_range(0..4) {
+                0 => rng.gen(),
+                1 => 0,
+                _ => rng.gen_range(0..1u64 << bits),
+            };
+            // Sometimes padded with leading zero groups.
+            let padding = if rng.gen_bool(0.2) {
+                rng.gen_range(1..4)
+            } else {
+                0
+            };
+            return Packet::Literal(Literal {
+                version,
+                value,
+                groups: min_groups(value) + padding,
+                offset: 0,
+            });
+        }
+
+        let operator = match rng.gen_range(0..7) {
+            0 => OperatorType::Sum,
+            1 => OperatorType::Product,
+            2 => OperatorType::Minimum,
+            3 => OperatorType::Maximum,
+            4 => OperatorType::GreaterThan,
+            5 => OperatorType::LessThan,
+            _ => OperatorType::EqualTo,
+        };
+        let arity = match operator {
+            OperatorType::GreaterThan | OperatorType::LessThan | OperatorType::EqualTo => 2,
+            _ => rng.gen_range(1..5),
+        };
+        Packet::Operator(Operator {
+            version,
+            operator,
+            length_type: rng.gen_range(0..2),
+            subpackets: (0..arity).map(|_| random_packet(rng, depth - 1)).collect(),
+            offset: 0,
+        })
+    }
+
+    // Re-encodes a transmission, padded out to the original's length.
+    fn reencode(hex: &str) -> String {
+        let input = decode_hex(hex).unwrap();
+        let (left, packet) = parse(&input).unwrap();
+        assert!(left.iter().all(|&b| b == 0), "{} has trailing data", hex);
+        let mut bytes = encode(&packet).unwrap();
+        assert_eq!(bytes.len(), input.len() - left.len(), "{}", hex);
+        bytes.resize(input.len(), 0);
+        to_hex(&bytes)
+    }
+
+    #[test]
+    fn random_packets_round_trip() {
+        let mut rng = StdRng::seed_from_u64(16);
+        for _ in 0..1000 {
+            let mut packet = random_packet(&mut rng, 4);
+            place(&mut packet, 0);
+            let bytes = encode(&packet).unwrap();
+            let (left, decoded) = parse(&bytes).unwrap();
+            assert_eq!(decoded, packet);
+            assert!(left.iter().all(|&b| b == 0), "left {:?}", left);
+        }
+    }
+
+    #[test]
+    fn transmissions_reencode_bit_for_bit() {
+        let transmissions = include_str!("input.txt")
+            .lines()
+            .chain(include_str!("example.txt").lines())
+            // A literal 5 written as two groups, the first all zeros.
+            .chain(["1205"]);
+        for hex in transmissions.map(str::trim).filter(|l| !l.is_empty()) {
+            assert_eq!(reencode(hex), hex.to_uppercase());
+        }
+    }
+
+    #[test]
+    fn unencodable_packets_are_errors() {
+        let literal = |version, value, groups| {
+            Packet::Literal(Literal {
+                version,
+                value,
+                groups,
+                offset: 0,
+            })
+        };
+        assert!(encode(&literal(8, 1, 1)).is_err());
+        assert!(encode(&literal(0, 16, 1)).is_err());
+        assert!(encode(&literal(0, 0, 0)).is_err());
+        let wide = Operator {
+            version: 0,
+            operator: OperatorType::Sum,
+            length_type: 1,
+            subpackets: (0..2048).map(|_| literal(0, 1, 1)).collect(),
+            offset: 0,
+        };
+        assert!(encode(&Packet::Operator(wide)).is_err());
+    }
+}
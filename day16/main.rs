@@ -1,36 +1,51 @@
+mod assemble;
+mod disasm;
+mod encode;
+
+use std::env;
+use std::fmt;
 use std::fs::File;
 use std::io::{self, BufRead};
+use std::iter;
 use std::path::Path;
+use std::process;
 
 use nom::bits::{bits, streaming::tag, streaming::take};
 use nom::branch::alt;
-use nom::combinator::{map, rest_len};
-use nom::error::Error;
-use nom::multi::{length_count, length_data, many0, many_m_n};
+use nom::combinator::{map, map_opt, rest_len};
+use nom::error::{Error, ErrorKind};
+use nom::multi::{length_count, many0};
 use nom::sequence::tuple;
 use nom::IResult;
 
-#[derive(Debug)]
+#[derive(Debug, PartialEq)]
 struct Literal {
     version: u8,
     value: u64,
+    // How many 4-bit groups the value was written with, which may include
+    // leading zero groups.
+    groups: usize,
+    // Where the packet starts in its transmission, in bits.
+    offset: usize,
 }
 
-#[derive(Debug)]
+#[derive(Debug, PartialEq)]
 struct Operator {
     version: u8,
     operator: OperatorType,
     length_type: u8,
     subpackets: Vec<Packet>,
+    // Where the packet starts in its transmission, in bits.
+    offset: usize,
 }
 
-#[derive(Debug)]
+#[derive(Debug, PartialEq)]
 enum Packet {
     Literal(Literal),
     Operator(Operator),
 }
 
-#[derive(Debug)]
+#[derive(Debug, PartialEq)]
 enum OperatorType {
     Sum,
     Product,
@@ -41,6 +56,10 @@ enum OperatorType {
     EqualTo,
 }
 
+// A position within the transmission: the remaining bytes and the bit offset
+// into the first of them.
+type Bits<'a> = (&'a [u8], usize);
+
 fn version(input: (&[u8], usize)) -> IResult<(&[u8], usize), u8> {
     take(3usize)(input)
 }
@@ -49,32 +68,36 @@ fn literal_id(input: (&[u8], usize)) -> IResult<(&[u8], usize), u8> {
     tag(0x04, 3usize)(input)
 }
 
-fn integer(input: (&[u8], usize)) -> IResult<(&[u8], usize), u64> {
+// The value and the number of groups it took.
+fn integer(input: (&[u8], usize)) -> IResult<(&[u8], usize), (u64, usize)> {
     let byte_with_next = map(tuple((tag(0x01, 1usize), take(4usize))), |(_, x)| x);
     let terminal_byte = map(tuple((tag(0x00, 1usize), take(4usize))), |(_, b)| b);
-    map(
+    // Fails rather than dropping bits if the value doesn't fit in a u64.
+    map_opt(
         tuple((many0(byte_with_next), terminal_byte)),
         |(parts, terminator): (Vec<u64>, _)| {
-            let mut out = terminator;
             parts
                 .iter()
-                .rev()
-                .enumerate()
-                .for_each(|(i, x)| out |= x << ((i + 1) * 4));
-            out
+                .chain(iter::once(&terminator))
+                .try_fold(0u64, |out, x| (out >> 60 == 0).then(|| out << 4 | x))
+                .map(|value| (value, parts.len() + 1))
         },
     )(input)
 }
 
+// While parsing, packets record how many bits were left when they started;
+// `parse` turns that into an offset from the start once the total is known.
 fn literal(input: (&[u8], usize)) -> IResult<(&[u8], usize), Packet> {
+    let (_, remaining) = rest_len(input)?;
     map(
         tuple((version, literal_id, integer)),
-        |(version, _, value)| -> Packet {
-            let p = Packet::Literal(Literal {
-                version: version,
-                value: value,
-            });
-            p
+        move |(version, _, (value, groups))| -> Packet {
+            Packet::Literal(Literal {
+                version,
+                value,
+                groups,
+                offset: remaining,
+            })
         },
     )(input)
 }
@@ -83,26 +106,35 @@ fn num_subpackets(input: (&[u8], usize)) -> IResult<(&[u8], usize), u16> {
     take(11usize)(input)
 }
 
-fn length_data_packets(
-) -> impl FnMut((&[u8], usize)) -> IResult<(&[u8], usize), Vec<Packet>, Error<(&[u8], usize)>> {
-    move |input: (&[u8], usize)| -> IResult<(&[u8], usize), Vec<Packet>, Error<(&[u8], usize)>> {
+fn length_data_packets<'a>(
+    depth: usize,
+) -> impl FnMut(Bits<'a>) -> IResult<Bits<'a>, Vec<Packet>, Error<Bits<'a>>> {
+    move |input: Bits<'a>| -> IResult<Bits<'a>, Vec<Packet>, Error<Bits<'a>>> {
         let (mut rest, num_bits) = take(15usize)(input)?;
         let (_, starting_bits) = rest_len(rest)?;
-        assert!(num_bits <= starting_bits);
+        let too_long = |at| nom::Err::Error(Error::new(at, ErrorKind::LengthValue));
+        if num_bits > starting_bits {
+            return Err(too_long(input));
+        }
         let mut bits_consumed = 0;
         let mut out = vec![];
-        while bits_consumed != num_bits {
-            let (new_rest, packet_data) = packet(rest)?;
+        while bits_consumed < num_bits {
+            let (new_rest, packet_data) = packet(rest, depth)?;
             bits_consumed = starting_bits - rest_len(new_rest)?.1;
             rest = new_rest;
             out.push(packet_data);
         }
+        // The last subpacket ran past the end of the length.
+        if bits_consumed != num_bits {
+            return Err(too_long(input));
+        }
 
         Ok((rest, out))
     }
 }
 
-fn operator(input: (&[u8], usize)) -> IResult<(&[u8], usize), Packet> {
+// `depth` is how deeply the operator's subpackets are nested.
+fn operator(input: (&[u8], usize), depth: usize) -> IResult<(&[u8], usize), Packet> {
     let operator_id = map(
         alt((
             tag(0x00, 3usize),
@@ -125,32 +157,62 @@ fn operator(input: (&[u8], usize)) -> IResult<(&[u8], usize), Packet> {
             _ => unreachable!(),
         },
     );
-    let subpackets_0 = tuple((tag(0x00, 1usize), length_data_packets()));
-    let subpackets_1 = tuple((tag(0x01, 1usize), length_count(num_subpackets, packet)));
+    let subpackets_0 = tuple((tag(0x00, 1usize), length_data_packets(depth)));
+    let subpackets_1 = tuple((
+        tag(0x01, 1usize),
+        length_count(num_subpackets, |input| packet(input, depth)),
+    ));
 
     let subpackets = alt((subpackets_0, subpackets_1));
 
+    let (_, remaining) = rest_len(input)?;
     map(
         tuple((version, operator_id, subpackets)),
-        |(version, operator, (length_type, subpackets))| -> Packet {
-            let p = Packet::Operator(Operator {
-                operator: operator,
-                version: version,
-                length_type: length_type,
-                subpackets: subpackets,
-            });
-
-            p
+        move |(version, operator, (length_type, subpackets))| -> Packet {
+            Packet::Operator(Operator {
+                operator,
+                version,
+                length_type,
+                subpackets,
+                offset: remaining,
+            })
         },
     )(input)
 }
 
-fn packet(input: (&[u8], usize)) -> IResult<(&[u8], usize), Packet> {
-    alt((literal, operator))(input)
+// The parser recurses once per level of nesting, so deeper transmissions
+// are rejected rather than left to overflow the stack.
+const MAX_DEPTH: usize = 128;
+
+// A packet nested inside `depth` operators.
+fn packet(input: (&[u8], usize), depth: usize) -> IResult<(&[u8], usize), Packet> {
+    if depth > MAX_DEPTH {
+        return Err(nom::Err::Failure(Error::new(input, ErrorKind::TooLarge)));
+    }
+    alt((literal, |input| operator(input, depth + 1)))(input)
 }
 
 fn parse(input: &[u8]) -> IResult<&[u8], Packet> {
-    bits::<_, _, Error<(&[u8], usize)>, _, _>(packet)(input)
+    let total = input.len() * 8;
+    let (rest, mut p) = bits::<_, _, Error<(&[u8], usize)>, _, _>(|input| packet(input, 0))(input)?;
+    p.measure_from(total);
+    Ok((rest, p))
+}
+
+impl Packet {
+    // Turns bits remaining at the start of each packet into offsets from the
+    // start of a `total`-bit transmission.
+    fn measure_from(&mut self, total: usize) {
+        match self {
+            Packet::Literal(lit) => lit.offset = total - lit.offset,
+            Packet::Operator(op) => {
+                op.offset = total - op.offset;
+                op.subpackets
+                    .iter_mut()
+                    .for_each(|sub| sub.measure_from(total));
+            }
+        }
+    }
 }
 
 fn sum_packet(p: &Packet) -> i32 {
@@ -165,144 +227,210 @@ fn sum_packet(p: &Packet) -> i32 {
     }
 }
 
-fn calculate_packet(p: &Packet) -> i64 {
-    match p {
-        Packet::Literal(lit) => lit.value as i64,
-        Packet::Operator(op) => match op.operator {
-            OperatorType::Sum => op
-                .subpackets
-                .iter()
-                .fold(0, |acc, x| acc + calculate_packet(x)),
-            OperatorType::Product => op
-                .subpackets
-                .iter()
-                .fold(1, |acc, x| acc * calculate_packet(x)),
-            OperatorType::Minimum => op
-                .subpackets
-                .iter()
-                .map(|x| calculate_packet(x))
-                .min()
-                .unwrap(),
-            OperatorType::Maximum => op
-                .subpackets
-                .iter()
-                .map(|x| calculate_packet(x))
-                .max()
-                .unwrap(),
-            OperatorType::GreaterThan => {
-                assert_eq!(op.subpackets.len(), 2);
-                if calculate_packet(&op.subpackets[0]) > calculate_packet(&op.subpackets[1]) {
-                    1
-                } else {
-                    0
-                }
-            }
-            OperatorType::LessThan => {
-                assert_eq!(op.subpackets.len(), 2);
-                if calculate_packet(&op.subpackets[0]) < calculate_packet(&op.subpackets[1]) {
-                    1
-                } else {
-                    0
-                }
+// Why a packet could not be evaluated. `offset` is the bit position of the
+// offending packet within the transmission.
+#[derive(Debug, PartialEq)]
+enum EvalError {
+    // A comparison without exactly two operands.
+    Arity {
+        offset: usize,
+        operator: &'static str,
+        found: usize,
+    },
+    // A minimum or maximum of nothing.
+    NoOperands {
+        offset: usize,
+        operator: &'static str,
+    },
+    // A literal or result outside the range of an i64.
+    Overflow {
+        offset: usize,
+        operator: &'static str,
+    },
+}
+
+impl fmt::Display for EvalError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            EvalError::Arity {
+                offset,
+                operator,
+                found,
+            } => write!(
+                f,
+                "bit {}: {} needs 2 operands, found {}",
+                offset, operator, found
+            ),
+            EvalError::NoOperands { offset, operator } => {
+                write!(f, "bit {}: {} of no operands", offset, operator)
             }
-            OperatorType::EqualTo => {
-                assert_eq!(op.subpackets.len(), 2);
-                if calculate_packet(&op.subpackets[0]) == calculate_packet(&op.subpackets[1]) {
-                    1
-                } else {
-                    0
-                }
+            EvalError::Overflow { offset, operator } => {
+                write!(f, "bit {}: {} overflows a 64-bit integer", offset, operator)
             }
-        },
+        }
+    }
+}
+
+fn calculate_packet(p: &Packet) -> Result<i64, EvalError> {
+    let op = match p {
+        Packet::Literal(lit) => {
+            return i64::try_from(lit.value).map_err(|_| EvalError::Overflow {
+                offset: lit.offset,
+                operator: "literal",
+            })
+        }
+        Packet::Operator(op) => op,
+    };
+
+    let values = op
+        .subpackets
+        .iter()
+        .map(calculate_packet)
+        .collect::<Result<Vec<_>, _>>()?;
+    let offset = op.offset;
+
+    let operator = op.operator.name();
+    let overflow = || EvalError::Overflow { offset, operator };
+    let empty = || EvalError::NoOperands { offset, operator };
+    let pair = || match values[..] {
+        [a, b] => Ok((a, b)),
+        _ => Err(EvalError::Arity {
+            offset,
+            operator,
+            found: values.len(),
+        }),
+    };
+    match op.operator {
+        OperatorType::Sum => values
+            .iter()
+            .try_fold(0i64, |acc, &x| acc.checked_add(x))
+            .ok_or_else(overflow),
+        OperatorType::Product => values
+            .iter()
+            .try_fold(1i64, |acc, &x| acc.checked_mul(x))
+            .ok_or_else(overflow),
+        OperatorType::Minimum => values.iter().copied().min().ok_or_else(empty),
+        OperatorType::Maximum => values.iter().copied().max().ok_or_else(empty),
+        OperatorType::GreaterThan => pair().map(|(a, b)| (a > b) as i64),
+        OperatorType::LessThan => pair().map(|(a, b)| (a < b) as i64),
+        OperatorType::EqualTo => pair().map(|(a, b)| (a == b) as i64),
     }
 }
 
 fn main() {
-    if let Ok(lines) = read_lines("./day16/example.txt") {
-        // let input = &[0xd2, 0xfe, 0x28];
-        // let input = &[0xEE, 0x00, 0xD4, 0x0C, 0x82, 0x30, 0x60];
-        // let input = &[0x8A, 0x00, 0x4A, 0x80, 0x1A, 0x80, 0x02, 0xF4, 0x78];
-        // let input = &[
-        //     0x62, 0x00, 0x80, 0x00, 0x16, 0x11, 0x56, 0x2C, 0x88, 0x02, 0x11, 0x8E, 0x34,
-        // ];
-        // let input = &[
-        //     0xC0, 0x01, 0x50, 0x00, 0x01, 0x61, 0x15, 0xA2, 0xE0, 0x80, 0x2F, 0x18, 0x23, 0x40,
-        // ];
-        // let input = &[
-        //     0xA0, 0x01, 0x6C, 0x88, 0x01, 0x62, 0x01, 0x7C, 0x36, 0x86, 0xB1, 0x8A, 0x3D, 0x47,
-        //     0x80,
-        // ];
-        // let input = &[0xC2, 0x00, 0xB4, 0x0A, 0x82];
-        // let input = &[0x04, 0x00, 0x5A, 0xC3, 0x38, 0x90];
-        // let input = &[0x88, 0x00, 0x86, 0xC3, 0xE8, 0x81, 0x12];
-        // let input = &[0xCE, 0x00, 0xC4, 0x3D, 0x88, 0x11, 0x20];
-        // let input = &[0xD8, 0x00, 0x5A, 0xC2, 0xA8, 0xF0];
-        // let input = &[0xF6, 0x00, 0xBC, 0x2D, 0x8F];
-        // let input = &[0x9C, 0x00, 0x5A, 0xC2, 0xF8, 0xF0];
-        // let input = &[
-        //     0x9C, 0x01, 0x41, 0x08, 0x02, 0x50, 0x32, 0x0F, 0x18, 0x02, 0x10, 0x4A, 0x08,
-        // ];
-        let input = &[
-            0x40, 0x54, 0x46, 0x08, 0x02, 0x53, 0x2B, 0x12, 0xFE, 0xE8, 0xB1, 0x80, 0x21, 0x3B,
-            0x19, 0xFA, 0x5A, 0xA7, 0x76, 0x01, 0xC0, 0x10, 0xE4, 0xEC, 0x25, 0x71, 0xA9, 0xED,
-            0xFE, 0x35, 0x6C, 0x70, 0x08, 0xE7, 0xB1, 0x41, 0x89, 0x8C, 0x1F, 0x4E, 0x50, 0xDA,
-            0x74, 0x38, 0xC0, 0x11, 0xD0, 0x05, 0xE4, 0xF6, 0xE7, 0x27, 0xB7, 0x38, 0xFC, 0x40,
-            0x18, 0x0C, 0xB3, 0xED, 0x80, 0x23, 0x23, 0xA8, 0xC3, 0xFE, 0xD8, 0xC4, 0xE8, 0x84,
-            0x42, 0x97, 0xD8, 0x8C, 0x57, 0x8C, 0x26, 0x00, 0x8E, 0x00, 0x43, 0x73, 0xBC, 0xA6,
-            0xB1, 0xC1, 0xC9, 0x99, 0x45, 0x42, 0x37, 0x98, 0x02, 0x58, 0x00, 0xD0, 0xCF, 0xF7,
-            0xDC, 0x19, 0x9C, 0x90, 0x94, 0xE3, 0x59, 0x80, 0x25, 0x3F, 0xB5, 0x0A, 0x00, 0xD4,
-            0xC4, 0x01, 0xB8, 0x71, 0x04, 0xA0, 0xC8, 0x00, 0x21, 0x71, 0xCE, 0x31, 0xC4, 0x12,
-            0x01, 0x06, 0x2C, 0x01, 0x39, 0x3A, 0xE2, 0xF5, 0xBC, 0xF7, 0xB6, 0xE9, 0x69, 0xF3,
-            0xC5, 0x53, 0xF2, 0xF0, 0xA1, 0x00, 0x91, 0xF2, 0xD7, 0x19, 0xC0, 0x0C, 0xD0, 0x40,
-            0x1A, 0x8F, 0xB1, 0xC6, 0x34, 0x08, 0x03, 0x30, 0x8A, 0x09, 0x47, 0xB3, 0x00, 0x56,
-            0x80, 0x33, 0x61, 0x00, 0x66, 0x15, 0xC4, 0x68, 0xE4, 0x20, 0x0E, 0x47, 0xE8, 0x41,
-            0x1D, 0x26, 0x69, 0x7F, 0xC3, 0xF9, 0x17, 0x40, 0x09, 0x4E, 0x16, 0x4D, 0xFA, 0x04,
-            0x53, 0xF4, 0x68, 0x99, 0x01, 0x50, 0x02, 0xA6, 0xE3, 0x9F, 0x3B, 0x98, 0x02, 0xB8,
-            0x00, 0xD0, 0x4A, 0x24, 0xCC, 0x76, 0x3E, 0xDB, 0xB4, 0xAF, 0xF9, 0x23, 0xA9, 0x6E,
-            0xD4, 0xBD, 0xC0, 0x1F, 0x87, 0x32, 0x9F, 0xA4, 0x91, 0xE0, 0x81, 0x80, 0x25, 0x3A,
-            0x4D, 0xE0, 0x08, 0x4C, 0x5B, 0x7F, 0x5B, 0x97, 0x8C, 0xC4, 0x10, 0x01, 0x2F, 0x9C,
-            0xFA, 0x84, 0xC9, 0x39, 0x00, 0xA5, 0x13, 0x5B, 0xD7, 0x39, 0x83, 0x5F, 0x00, 0x54,
-            0x00, 0x10, 0xF8, 0xBF, 0x1D, 0x22, 0xA0, 0x80, 0x37, 0x06, 0xE0, 0xA4, 0x7B, 0x30,
-            0x09, 0xA5, 0x87, 0xE7, 0xD5, 0xE4, 0xD3, 0xA5, 0x9B, 0x4C, 0x00, 0xE9, 0x56, 0x73,
-            0x00, 0xAE, 0x79, 0x1E, 0x0D, 0xCA, 0x3C, 0x4A, 0x32, 0xCD, 0xBD, 0xC4, 0x83, 0x00,
-            0x56, 0x63, 0x9D, 0x57, 0xC0, 0x0D, 0x4C, 0x40, 0x1C, 0x87, 0x91, 0x16, 0x23, 0x80,
-            0x02, 0x11, 0x08, 0xE2, 0x6C, 0x6D, 0x99, 0x1D, 0x10, 0x08, 0x25, 0x49, 0x21, 0x8C,
-            0xDC, 0x67, 0x14, 0x79, 0xA9, 0x72, 0x33, 0xD4, 0x39, 0x93, 0xD7, 0x00, 0x56, 0x66,
-            0x3F, 0xAC, 0x63, 0x0C, 0xB4, 0x4D, 0x2E, 0x38, 0x05, 0x92, 0xFB, 0x93, 0xC4, 0xF4,
-            0x0C, 0xA7, 0xD1, 0xA6, 0x0F, 0xE6, 0x43, 0x48, 0x03, 0x9C, 0xE0, 0x06, 0x9E, 0x5F,
-            0x56, 0x56, 0x97, 0xD5, 0x94, 0x24, 0xB9, 0x2A, 0xF2, 0x46, 0xAC, 0x06, 0x5D, 0xB0,
-            0x18, 0x12, 0x80, 0x5A, 0xD9, 0x01, 0x55, 0x20, 0x04, 0xFD, 0xB8, 0x01, 0xE2, 0x00,
-            0x73, 0x80, 0x16, 0x40, 0x3C, 0xC0, 0x00, 0xDD, 0x2E, 0x00, 0x53, 0x80, 0x1E, 0x60,
-            0x07, 0x00, 0x09, 0x1A, 0x80, 0x1E, 0xD2, 0x00, 0x65, 0xE6, 0x00, 0x71, 0x80, 0x1A,
-            0x80, 0x0A, 0xEB, 0x00, 0x15, 0x13, 0x16, 0x45, 0x00, 0x14, 0x38, 0x80, 0x10, 0xB8,
-            0x61, 0x05, 0xE1, 0x39, 0x80, 0x35, 0x04, 0x23, 0xF4, 0x47, 0x20, 0x04, 0x36, 0x16,
-            0x46, 0x88, 0xA4, 0x00, 0x1E, 0x04, 0x88, 0xAC, 0x90, 0xFC, 0xDF, 0x31, 0x07, 0x49,
-            0x29, 0x45, 0x2E, 0x76, 0x12, 0xB1, 0x51, 0x80, 0x3A, 0x20, 0x0E, 0xC3, 0x98, 0x67,
-            0x0E, 0x84, 0x01, 0xB8, 0x2D, 0x04, 0xE3, 0x18, 0x80, 0x39, 0x04, 0x63, 0x44, 0x65,
-            0x20, 0x04, 0x0A, 0x44, 0xAA, 0x71, 0xC2, 0x56, 0x53, 0xB6, 0xF2, 0xFE, 0x80, 0x12,
-            0x4C, 0x9F, 0xF1, 0x8E, 0xDF, 0xCA, 0x10, 0x92, 0x75, 0xA1, 0x40, 0x28, 0x9C, 0xDF,
-            0x7B, 0x3A, 0xEE, 0xB0, 0xC9, 0x54, 0xF4, 0xB5, 0xFC, 0x7C, 0xD2, 0x62, 0x3E, 0x85,
-            0x97, 0x26, 0xFB, 0x6E, 0x57, 0xDA, 0x49, 0x9E, 0xA7, 0x7B, 0x6B, 0x68, 0xE0, 0x40,
-            0x1D, 0x99, 0x6D, 0x9C, 0x42, 0x92, 0xA8, 0x81, 0x80, 0x39, 0x26, 0xFB, 0x26, 0x23,
-            0x2A, 0x13, 0x35, 0x98, 0xA1, 0x18, 0x02, 0x34, 0x00, 0xFA, 0x4A, 0xDA, 0xDD, 0x5A,
-            0x97, 0xCE, 0xEC, 0x0D, 0x37, 0x69, 0x6F, 0xC0, 0xE6, 0x00, 0x9D, 0x00, 0x2A, 0x93,
-            0x7B, 0x45, 0x9B, 0xDA, 0x3C, 0xC7, 0xFF, 0xD6, 0x52, 0x00, 0xF2, 0xE5, 0x31, 0x58,
-            0x1A, 0xD8, 0x02, 0x30, 0x32, 0x6E, 0x11, 0xF5, 0x2D, 0xFA, 0xEA, 0xAA, 0x11, 0xDC,
-            0xC0, 0x10, 0x91, 0xD8, 0xBE, 0x00, 0x39, 0xB2, 0x96, 0xAB, 0x9C, 0xE5, 0xB5, 0x76,
-            0x13, 0x00, 0x53, 0x00, 0x15, 0x29, 0xBE, 0x38, 0xCD, 0xF1, 0xD2, 0x2C, 0x10, 0x05,
-            0x09, 0x29, 0x8B, 0x99, 0x50, 0x02, 0x0B, 0x30, 0x9B, 0x30, 0x98, 0xC0, 0x02, 0xF4,
-            0x19, 0x10, 0x02, 0x26, 0xDC,
-        ];
-        let (left, packet) = parse(input).unwrap();
-        // let (_, packet) = parse_packet(input).unwrap();
-        println!("{:?}", packet);
+    let mut args: Vec<String> = env::args().skip(1).collect();
+    // Print each packet on one line as arithmetic instead of as a tree.
+    let infix = args.first().map(String::as_str) == Some("infix");
+    if infix {
+        args.remove(0);
+    }
+    // Read expressions and print them as transmissions instead.
+    let asm = args.first().map(String::as_str) == Some("asm");
+    if asm {
+        args.remove(0);
+    }
+
+    // A file of transmissions (or expressions), one per line, or `-` for
+    // stdin.
+    let path = args.first().cloned().unwrap_or_else(|| {
+        if asm {
+            "./day16/programs.txt".to_string()
+        } else {
+            "./day16/input.txt".to_string()
+        }
+    });
+    let lines: Vec<String> = if path == "-" {
+        io::stdin().lock().lines().map_while(Result::ok).collect()
+    } else {
+        match read_lines(&path) {
+            Ok(lines) => lines.map_while(Result::ok).collect(),
+            Err(e) => {
+                eprintln!("{}: {}", path, e);
+                process::exit(1);
+            }
+        }
+    };
+
+    if asm {
+        for (i, line) in lines.iter().enumerate() {
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+            match assemble::assemble(line).and_then(|p| encode::encode(&p)) {
+                Ok(bytes) => println!("{}", encode::to_hex(&bytes)),
+                Err(e) => eprintln!("line {}: {}", i + 1, e),
+            }
+        }
+        return;
+    }
+
+    for (i, line) in lines.iter().enumerate() {
+        let line = line.trim();
+        if line.is_empty() {
+            continue;
+        }
+        println!("line {}:", i + 1);
+
+        let input = match decode_hex(line) {
+            Ok(input) => input,
+            Err(e) => {
+                eprintln!("line {}: {}", i + 1, e);
+                continue;
+            }
+        };
+        let (left, packet) = match parse(&input) {
+            Ok(parsed) => parsed,
+            Err(nom::Err::Failure(Error {
+                code: ErrorKind::TooLarge,
+                ..
+            })) => {
+                eprintln!(
+                    "line {}: packets are nested more than {} deep",
+                    i + 1,
+                    MAX_DEPTH
+                );
+                continue;
+            }
+            Err(e) => {
+                eprintln!("line {}: invalid transmission: {:?}", i + 1, e);
+                continue;
+            }
+        };
+        if infix {
+            println!("{}", disasm::infix(&packet));
+        } else {
+            println!("{}", disasm::sexpr(&packet));
+        }
         println!("{:?}", left);
 
         println!("{:?}", sum_packet(&packet));
-        println!("{:?}", calculate_packet(&packet));
+        match calculate_packet(&packet) {
+            Ok(value) => println!("{:?}", value),
+            Err(e) => eprintln!("line {}: {}", i + 1, e),
+        }
     }
 }
 
+fn decode_hex(s: &str) -> Result<Vec<u8>, String> {
+    if !s.len().is_multiple_of(2) {
+        return Err(format!("odd number of hex digits ({})", s.len()));
+    }
+    let digits: Vec<u8> = s
+        .chars()
+        .enumerate()
+        .map(|(i, c)| {
+            c.to_digit(16)
+                .map(|d| d as u8)
+                .ok_or_else(|| format!("invalid hex digit {:?} at column {}", c, i + 1))
+        })
+        .collect::<Result<_, _>>()?;
+    Ok(digits
+        .chunks(2)
+        .map(|pair| pair[0] << 4 | pair[1])
+        .collect())
+}
+
 // The output is wrapped in a Result to allow matching on errors
 // Returns an Iterator to the Reader of the lines of the file.
 fn read_lines<P>(filename: P) -> io::Result<io::Lines<io::BufReader<File>>>
@@ -312,3 +440,147 @@ where
     let file = File::open(filename)?;
     Ok(io::BufReader::new(file).lines())
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn decode(hex: &str) -> Result<Packet, String> {
+        let input = decode_hex(hex)?;
+        parse(&input)
+            .map(|(_, packet)| packet)
+            .map_err(|e| format!("{:?}", e))
+    }
+
+    // Malformed programs evaluate to an error at the offending packet.
+    #[test]
+    fn evaluation_errors() {
+        let cases = [
+            ("eq(sum(1, 3), product(2, 2))", Ok(1)),
+            (
+                "sum(min(), 1)",
+                Err(EvalError::NoOperands {
+                    offset: 22,
+                    operator: "min",
+                }),
+            ),
+            (
+                "sum(1, gt(2, 3, 4))",
+                Err(EvalError::Arity {
+                    offset: 33,
+                    operator: "gt",
+                    found: 3,
+                }),
+            ),
+            (
+                "lt:count(1)",
+                Err(EvalError::Arity {
+                    offset: 0,
+                    operator: "lt",
+                    found: 1,
+                }),
+            ),
+            (
+                "product(4294967296, 2147483648)",
+                Err(EvalError::Overflow {
+                    offset: 0,
+                    operator: "product",
+                }),
+            ),
+            (
+                "max(1, 9223372036854775808)",
+                Err(EvalError::Overflow {
+                    offset: 33,
+                    operator: "literal",
+                }),
+            ),
+            ("sum(9223372036854775807, 0)", Ok(i64::MAX)),
+        ];
+        for (source, expected) in cases {
+            let packet = assemble::assemble(source).unwrap();
+            assert_eq!(calculate_packet(&packet), expected, "{:?}", source);
+        }
+    }
+
+    // Offsets come from the transmission, so a literal padded with leading
+    // zero groups moves everything after it.
+    #[test]
+    fn evaluation_errors_point_into_the_stream() {
+        let mut program = assemble::assemble("sum(5, gt(1, 2, 3))").unwrap();
+        if let Packet::Operator(op) = &mut program {
+            if let Packet::Literal(lit) = &mut op.subpackets[0] {
+                lit.groups = 3;
+            }
+        }
+        let hex = encode::to_hex(&encode::encode(&program).unwrap());
+        assert_eq!(
+            calculate_packet(&decode(&hex).unwrap()),
+            Err(EvalError::Arity {
+                offset: 43,
+                operator: "gt",
+                found: 3,
+            })
+        );
+        assert_eq!(
+            calculate_packet(&decode("1600C481440881").unwrap()),
+            Err(EvalError::Arity {
+                offset: 0,
+                operator: "gt",
+                found: 3,
+            })
+        );
+    }
+
+    #[test]
+    fn malformed_transmissions_are_errors() {
+        // Operator whose subpackets claim more bits than remain.
+        assert!(decode("0000FFFF").is_err());
+        // Literal with more groups than fit in a u64.
+        assert!(decode("12318C6318C6318C6318C621").is_err());
+        // Literal cut off mid-group.
+        assert!(decode("1231").is_err());
+        assert!(decode("D2FE2").is_err());
+        assert!(decode("D2FEZ8").is_err());
+    }
+
+    // `depth` sums, each with a count of one, around a literal 1.
+    fn nested(depth: usize) -> Vec<u8> {
+        let mut bits = vec![];
+        let mut push = |value: u64, width: usize| {
+            bits.extend((0..width).rev().map(|i| value >> i & 1 == 1));
+        };
+        for _ in 0..depth {
+            // Version 0, sum, length type 1, one subpacket.
+            push(0, 6);
+            push(1, 1);
+            push(1, 11);
+        }
+        // Version 0, literal, a single group holding 1.
+        push(0, 3);
+        push(4, 3);
+        push(1, 5);
+        bits.chunks(8)
+            .map(|chunk| {
+                chunk
+                    .iter()
+                    .enumerate()
+                    .fold(0, |acc, (i, &bit)| acc | (bit as u8) << (7 - i))
+            })
+            .collect()
+    }
+
+    #[test]
+    fn nesting_is_limited() {
+        let (_, packet) = parse(&nested(MAX_DEPTH)).unwrap();
+        assert_eq!(calculate_packet(&packet), Ok(1));
+        for depth in [MAX_DEPTH + 1, 20_000] {
+            assert!(matches!(
+                parse(&nested(depth)),
+                Err(nom::Err::Failure(Error {
+                    code: ErrorKind::TooLarge,
+                    ..
+                }))
+            ));
+        }
+    }
+}
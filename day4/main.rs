@@ -1,100 +1,675 @@
 use grid::Grid;
-use itertools::Itertools;
-use std::collections::BTreeSet;
-use std::fs::File;
+use rand::rngs::StdRng;
+use rand::seq::{index, SliceRandom};
+use rand::SeedableRng;
+use std::collections::{BTreeSet, HashMap};
+use std::env;
+use std::fmt;
+use std::fs::{self, File};
 use std::io::{self, BufRead};
 use std::path::Path;
+use std::process;
+use std::str::FromStr;
+
+#[derive(Debug, PartialEq)]
+enum ParseError {
+    MissingDrawing,
+    NoBoards,
+    BadNumber {
+        // None for the drawing.
+        board: Option<usize>,
+        line: usize,
+        token: String,
+    },
+    RaggedRow {
+        board: usize,
+        line: usize,
+        expected: usize,
+        found: usize,
+    },
+    BoardHeight {
+        board: usize,
+        line: usize,
+        expected: usize,
+        found: usize,
+    },
+}
+
+impl fmt::Display for ParseError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            ParseError::MissingDrawing => write!(f, "missing drawing on line 1"),
+            ParseError::NoBoards => write!(f, "no boards after the drawing"),
+            ParseError::BadNumber {
+                board: None,
+                line,
+                token,
+            } => write!(f, "line {}: invalid number {:?}", line, token),
+            ParseError::BadNumber {
+                board: Some(board),
+                line,
+                token,
+            } => write!(
+                f,
+                "board {}, line {}: invalid number {:?}",
+                board, line, token
+            ),
+            ParseError::RaggedRow {
+                board,
+                line,
+                expected,
+                found,
+            } => write!(
+                f,
+                "board {}, line {}: row has {} cells, expected {}",
+                board, line, found, expected
+            ),
+            ParseError::BoardHeight {
+                board,
+                line,
+                expected,
+                found,
+            } => write!(
+                f,
+                "board {}, line {}: board has {} rows, expected {}",
+                board, line, found, expected
+            ),
+        }
+    }
+}
+
+#[derive(Debug)]
+enum WinPattern {
+    Row,
+    Column,
+    Diagonal,
+    FourCorners,
+    Blackout,
+    XShape,
+    Mask(String, Grid<bool>),
+}
+
+impl fmt::Display for WinPattern {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            WinPattern::Row => write!(f, "row"),
+            WinPattern::Column => write!(f, "column"),
+            WinPattern::Diagonal => write!(f, "diagonal"),
+            WinPattern::FourCorners => write!(f, "four corners"),
+            WinPattern::Blackout => write!(f, "blackout"),
+            WinPattern::XShape => write!(f, "x"),
+            WinPattern::Mask(name, _) => write!(f, "mask {:?}", name),
+        }
+    }
+}
+
+impl WinPattern {
+    // Each pattern is a list of alternative cell sets; the pattern fires when
+    // any one of them is fully marked.
+    fn cell_sets(&self, rows: usize, cols: usize) -> Vec<Vec<(usize, usize)>> {
+        // Both diagonals of a square board.
+        let diagonals = || {
+            let leading: Vec<_> = (0..rows).map(|i| (i, i)).collect();
+            let trailing: Vec<_> = (0..rows).map(|i| (i, cols - 1 - i)).collect();
+            (leading, trailing)
+        };
+        match self {
+            WinPattern::Row => (0..rows)
+                .map(|r| (0..cols).map(|c| (r, c)).collect())
+                .collect(),
+            WinPattern::Column => (0..cols)
+                .map(|c| (0..rows).map(|r| (r, c)).collect())
+                .collect(),
+            // Diagonals only make sense on square boards.
+            WinPattern::Diagonal if rows == cols => {
+                let (leading, trailing) = diagonals();
+                vec![leading, trailing]
+            }
+            WinPattern::XShape if rows == cols => {
+                let (leading, trailing) = diagonals();
+                vec![leading.into_iter().chain(trailing).collect()]
+            }
+            WinPattern::Diagonal | WinPattern::XShape => vec![],
+            WinPattern::FourCorners => vec![vec![
+                (0, 0),
+                (0, cols - 1),
+                (rows - 1, 0),
+                (rows - 1, cols - 1),
+            ]],
+            WinPattern::Blackout => vec![(0..rows)
+                .flat_map(|r| (0..cols).map(move |c| (r, c)))
+                .collect()],
+            WinPattern::Mask(_, mask) if mask.size() == (rows, cols) => vec![(0..rows)
+                .flat_map(|r| (0..cols).map(move |c| (r, c)))
+                .filter(|(r, c)| mask[*r][*c])
+                .collect()],
+            WinPattern::Mask(..) => vec![],
+        }
+    }
+
+    // Masks are drawn for one board size; anything else would never fire.
+    fn check_size(&self, rows: usize, cols: usize) -> Result<(), String> {
+        match self {
+            WinPattern::Mask(name, mask) if mask.size() != (rows, cols) => Err(format!(
+                "mask {:?} is {}x{} but the boards are {}x{}",
+                name,
+                mask.rows(),
+                mask.cols(),
+                rows,
+                cols
+            )),
+            _ => Ok(()),
+        }
+    }
+
+    fn fires(&self, marks: &Grid<bool>) -> bool {
+        self.cell_sets(marks.rows(), marks.cols())
+            .iter()
+            .any(|cells| cells.iter().all(|(r, c)| marks[*r][*c]))
+    }
+}
+
+enum Mode {
+    Play,
+    // Number of shuffled draw orders to try, and the RNG seed.
+    Simulate { trials: usize, seed: u64 },
+}
+
+// Shape of the random input written by `generate`.
+struct GeneratorConfig {
+    boards: usize,
+    rows: usize,
+    cols: usize,
+    // Inclusive range that board numbers and draws come from.
+    lo: u32,
+    hi: u32,
+    seed: u64,
+}
 
 fn main() {
+    let mut args: Vec<String> = env::args().skip(1).collect();
+    if args.first().map(|a| a.as_str()) == Some("generate") {
+        let written = generator_options(&args[1..])
+            .and_then(|config| generate(&config))
+            .and_then(|contents| match args.get(7) {
+                Some(path) => fs::write(path, contents).map_err(|e| format!("{}: {}", path, e)),
+                None => {
+                    print!("{}", contents);
+                    Ok(())
+                }
+            });
+        if let Err(e) = written {
+            eprintln!("{}", e);
+            process::exit(1);
+        }
+        return;
+    }
+
+    let mode = if args.first().map(|a| a.as_str()) == Some("simulate") {
+        args.remove(0);
+        match simulate_options(&mut args) {
+            Ok(mode) => mode,
+            Err(e) => {
+                eprintln!("{}", e);
+                process::exit(1);
+            }
+        }
+    } else {
+        Mode::Play
+    };
+
+    let patterns = match parse_patterns(args.into_iter()) {
+        Ok(patterns) => patterns,
+        Err(e) => {
+            eprintln!("{}", e);
+            process::exit(1);
+        }
+    };
+    println!(
+        "win patterns: {}",
+        patterns
+            .iter()
+            .map(|p| p.to_string())
+            .collect::<Vec<_>>()
+            .join(", ")
+    );
+
     if let Ok(lines) = read_lines("./day4/input.txt") {
-        let ls: Vec<String> = lines.filter_map(|line| line.ok()).collect();
-        let drawing: Vec<u32> = ls
-            .first()
-            .unwrap()
-            .split(",")
-            .map(|s| s.parse().unwrap())
-            .collect();
+        let ls: Vec<String> = lines.map_while(Result::ok).collect();
+        let (drawing, grids) = match parse_input(&ls) {
+            Ok(parsed) => parsed,
+            Err(e) => {
+                eprintln!("{}", e);
+                process::exit(1);
+            }
+        };
+        if let Some(g) = grids.first() {
+            if let Err(e) = patterns
+                .iter()
+                .try_for_each(|p| p.check_size(g.rows(), g.cols()))
+            {
+                eprintln!("{}", e);
+                process::exit(1);
+            }
+        }
+
+        match mode {
+            Mode::Play => play(&drawing, &grids, &patterns),
+            Mode::Simulate { trials, seed } => {
+                let stats = simulate(&drawing, &grids, &patterns, trials, seed);
+                print_stats(&stats, trials);
+            }
+        }
+    }
+}
+
+// Takes `trials=<n>` and `seed=<n>` out of the arguments, leaving the win
+// patterns behind.
+fn simulate_options(args: &mut Vec<String>) -> Result<Mode, String> {
+    let mut trials = 1000;
+    let mut seed = 0;
+    let mut rest = vec![];
+    for arg in args.drain(..) {
+        if let Some(n) = arg.strip_prefix("trials=") {
+            trials = n
+                .parse()
+                .ok()
+                .filter(|t| *t > 0)
+                .ok_or_else(|| format!("invalid trial count {:?}", n))?;
+        } else if let Some(n) = arg.strip_prefix("seed=") {
+            seed = n.parse().map_err(|_| format!("invalid seed {:?}", n))?;
+        } else {
+            rest.push(arg);
+        }
+    }
+    *args = rest;
+    Ok(Mode::Simulate { trials, seed })
+}
+
+// `<boards> <rows> <cols> <lo> <hi> <seed>`, each optional from the right.
+fn generator_options(args: &[String]) -> Result<GeneratorConfig, String> {
+    fn arg<T: FromStr>(args: &[String], i: usize, name: &str, default: T) -> Result<T, String> {
+        match args.get(i) {
+            Some(a) => a.parse().map_err(|_| format!("invalid {} {:?}", name, a)),
+            None => Ok(default),
+        }
+    }
+    Ok(GeneratorConfig {
+        boards: arg(args, 0, "board count", 100)?,
+        rows: arg(args, 1, "row count", 5)?,
+        cols: arg(args, 2, "column count", 5)?,
+        lo: arg(args, 3, "lowest number", 0)?,
+        hi: arg(args, 4, "highest number", 99)?,
+        seed: arg(args, 5, "seed", 0)?,
+    })
+}
 
-        let grid_rows: Vec<_> = ls
+fn play(drawing: &[u32], grids: &[Grid<u32>], patterns: &[WinPattern]) {
+    let mut previous_winners: BTreeSet<usize> = BTreeSet::new();
+
+    for i in 0..drawing.len() {
+        let so_far = &drawing[0..i];
+        let winners: Vec<(usize, &WinPattern)> = grids
             .iter()
-            .skip(1)
-            .filter(|l| !l.is_empty())
-            .map(|row| {
-                row.split_whitespace()
-                    .map(|cell| cell.parse::<u32>().unwrap())
-                    .collect::<Vec<_>>()
-            })
+            .enumerate()
+            .filter_map(|(i, g)| winning_pattern(g, so_far, patterns).map(|p| (i, p)))
+            .collect();
+        let new_winners: BTreeSet<usize> = winners
+            .iter()
+            .map(|(i, _p)| *i)
+            .filter(|i| !previous_winners.contains(i))
             .collect();
+        winners
+            .iter()
+            .filter(|(b, _p)| new_winners.contains(b))
+            .for_each(|(b, p)| println!("board {} wins after {} draws with {}", b + 1, i, p));
+
+        if previous_winners.union(&new_winners).count() == grids.len() {
+            // This is the final winner!
+            let last = grids.get(*new_winners.iter().next().unwrap()).unwrap();
+            println!("last grid: {:?}", last);
+
+            let sum: u32 = last.iter().filter(|c| !so_far.contains(c)).sum();
+            let last_draw = so_far.last().unwrap();
+            println!(
+                "sum: {} last_draw: {} score: {}",
+                sum,
+                last_draw,
+                sum * last_draw
+            );
+            return;
+        }
+
+        new_winners.iter().for_each(|i| {
+            previous_winners.insert(*i);
+        });
+    }
+}
+
+#[derive(Debug, Default, Clone)]
+struct BoardStats {
+    // Trials in which this board was first to win (ties count for everyone).
+    wins: usize,
+    // Trials in which this board completed a pattern at all.
+    completions: usize,
+    total_win_turn: usize,
+}
+
+// Plays `trials` games, each with the draw pool shuffled by a seeded RNG so
+// that runs are reproducible.
+fn simulate(
+    drawing: &[u32],
+    grids: &[Grid<u32>],
+    patterns: &[WinPattern],
+    trials: usize,
+    seed: u64,
+) -> Vec<BoardStats> {
+    let mut rng = StdRng::seed_from_u64(seed);
+    let mut pool = drawing.to_vec();
+    let mut stats = vec![BoardStats::default(); grids.len()];
 
-        let grids: Vec<Grid<u32>> = grid_rows
+    for _ in 0..trials {
+        pool.shuffle(&mut rng);
+        // 1-based turn on which each number is drawn.
+        let turns: HashMap<u32, usize> =
+            pool.iter().enumerate().map(|(i, n)| (*n, i + 1)).collect();
+
+        let win_turns: Vec<Option<usize>> = grids
             .iter()
-            .tuples::<(_, _, _, _, _)>()
-            .map(|(a, b, c, d, e)| {
-                let mut g = Grid::new(1, 1);
-                g.clear();
-                g.push_row(a.to_vec());
-                g.push_row(b.to_vec());
-                g.push_row(c.to_vec());
-                g.push_row(d.to_vec());
-                g.push_row(e.to_vec());
-                assert_eq!(g.rows(), 5);
-                assert_eq!(g.cols(), 5);
-                g
-            })
+            .map(|g| win_turn(g, patterns, &turns))
             .collect();
+        let first = win_turns.iter().flatten().min().copied();
+
+        for (s, turn) in stats.iter_mut().zip(win_turns) {
+            if let Some(turn) = turn {
+                s.completions += 1;
+                s.total_win_turn += turn;
+                if Some(turn) == first {
+                    s.wins += 1;
+                }
+            }
+        }
+    }
 
-        let mut previous_winners: BTreeSet<usize> = BTreeSet::new();
+    stats
+}
 
-        for i in 0..drawing.len() {
-            let so_far = drawing[0..i].to_vec();
-            let winners: BTreeSet<usize> = grids
+// The turn on which `grid` first completes any pattern, given the turn each
+// number is drawn on. Cells whose number is never drawn can't be marked.
+fn win_turn(
+    grid: &Grid<u32>,
+    patterns: &[WinPattern],
+    turns: &HashMap<u32, usize>,
+) -> Option<usize> {
+    patterns
+        .iter()
+        .flat_map(|p| p.cell_sets(grid.rows(), grid.cols()))
+        .filter_map(|cells| {
+            cells
                 .iter()
-                .enumerate()
-                .filter(|(_i, g)| is_winner(&g, &so_far))
-                .map(|(i, _g)| i)
+                .map(|(r, c)| turns.get(&grid[*r][*c]).copied())
+                .try_fold(0, |acc, t| t.map(|t| acc.max(t)))
+        })
+        .min()
+}
+
+fn print_stats(stats: &[BoardStats], trials: usize) {
+    let mut ranked: Vec<_> = stats.iter().enumerate().collect();
+    ranked.sort_by_key(|(_, s)| std::cmp::Reverse(s.wins));
+
+    println!("{:>6} {:>10} {:>10}", "board", "win prob", "mean turn");
+    for (i, s) in ranked {
+        let mean_turn = if s.completions > 0 {
+            format!("{:.2}", s.total_win_turn as f64 / s.completions as f64)
+        } else {
+            "-".to_string()
+        };
+        println!(
+            "{:>6} {:>10.4} {:>10}",
+            i + 1,
+            s.wins as f64 / trials as f64,
+            mean_turn
+        );
+    }
+}
+
+// Produces random boards and a draw sequence in the same format as
+// `input.txt`. The draw sequence is a shuffle of the whole number range.
+fn generate(config: &GeneratorConfig) -> Result<String, String> {
+    if config.lo > config.hi {
+        return Err(format!("empty number range {}..={}", config.lo, config.hi));
+    }
+    let range = (config.hi - config.lo) as usize + 1;
+    let cells = config.rows * config.cols;
+    if cells == 0 || cells > range {
+        return Err(format!(
+            "can't fill a {}x{} board without duplicates from {} numbers",
+            config.rows, config.cols, range
+        ));
+    }
+
+    let mut rng = StdRng::seed_from_u64(config.seed);
+    let mut drawing: Vec<u32> = (config.lo..=config.hi).collect();
+    drawing.shuffle(&mut rng);
+
+    let grids: Vec<Grid<u32>> = (0..config.boards)
+        .map(|_| {
+            let picked = index::sample(&mut rng, range, cells)
+                .iter()
+                .map(|i| config.lo + i as u32)
                 .collect();
-            let new_winners: BTreeSet<usize> =
-                winners.difference(&previous_winners).map(|i| *i).collect();
-
-            if previous_winners.union(&new_winners).count() == grids.len() {
-                // This is the final winner!
-                let last = grids.get(*new_winners.iter().next().unwrap()).unwrap();
-                println!("last grid: {:?}", last);
-
-                let sum: u32 = last.iter().filter(|c| !so_far.contains(c)).sum();
-                let last_draw = so_far.last().unwrap();
-                println!(
-                    "sum: {} last_draw: {} score: {}",
-                    sum,
-                    last_draw,
-                    sum * last_draw
-                );
-                return;
-            }
+            Grid::from_vec(picked, config.cols)
+        })
+        .collect();
+
+    Ok(format_input(&drawing, &grids))
+}
+
+fn format_input(drawing: &[u32], grids: &[Grid<u32>]) -> String {
+    let width = grids
+        .iter()
+        .flat_map(|g| g.iter())
+        .map(|n| n.to_string().len())
+        .max()
+        .unwrap_or(1);
+
+    let mut out = drawing
+        .iter()
+        .map(|n| n.to_string())
+        .collect::<Vec<_>>()
+        .join(",");
+    out.push('\n');
+    for g in grids {
+        out.push('\n');
+        for r in 0..g.rows() {
+            let row: Vec<String> = g.iter_row(r).map(|n| format!("{:>width$}", n)).collect();
+            out.push_str(&row.join(" "));
+            out.push('\n');
+        }
+    }
+    out
+}
+
+// The first line is the comma-separated drawing, followed by boards as
+// blank-line-separated blocks. Every board must have the same dimensions, but
+// they needn't be square. When they disagree, the dimensions most boards have
+// are taken as right, so the error points at the odd one out.
+fn parse_input(ls: &[String]) -> Result<(Vec<u32>, Vec<Grid<u32>>), ParseError> {
+    let first = ls.first().ok_or(ParseError::MissingDrawing)?;
+    let drawing = first
+        .split(',')
+        .map(|s| parse_number(s.trim(), None, 1))
+        .collect::<Result<Vec<u32>, _>>()?;
+
+    // Group the remaining lines into blocks, remembering where each starts.
+    let mut blocks: Vec<(usize, Vec<(usize, &String)>)> = vec![];
+    let mut in_block = false;
+    for (i, l) in ls.iter().enumerate().skip(1) {
+        let line = i + 1;
+        if l.trim().is_empty() {
+            in_block = false;
+            continue;
+        }
+        if !in_block {
+            blocks.push((line, vec![]));
+            in_block = true;
+        }
+        blocks.last_mut().unwrap().1.push((line, l));
+    }
+    if blocks.is_empty() {
+        return Err(ParseError::NoBoards);
+    }
+
+    // Each board as (first line, [(line, row)]).
+    let mut boards = vec![];
+    for (b, (start, rows)) in blocks.iter().enumerate() {
+        let rows = rows
+            .iter()
+            .map(|(line, row)| {
+                row.split_whitespace()
+                    .map(|cell| parse_number(cell, Some(b + 1), *line))
+                    .collect::<Result<Vec<u32>, _>>()
+                    .map(|cells| (*line, cells))
+            })
+            .collect::<Result<Vec<_>, _>>()?;
+        boards.push((*start, rows));
+    }
 
-            new_winners.iter().for_each(|i| {
-                previous_winners.insert(*i);
+    let cols = most_common(
+        boards
+            .iter()
+            .flat_map(|(_, rows)| rows.iter().map(|(_, cells)| cells.len())),
+    );
+    let height = most_common(boards.iter().map(|(_, rows)| rows.len()));
+
+    let mut grids = vec![];
+    for (b, (start, rows)) in boards.into_iter().enumerate() {
+        let board = b + 1;
+        if let Some((line, cells)) = rows.iter().find(|(_, cells)| cells.len() != cols) {
+            return Err(ParseError::RaggedRow {
+                board,
+                line: *line,
+                expected: cols,
+                found: cells.len(),
+            });
+        }
+        if rows.len() != height {
+            return Err(ParseError::BoardHeight {
+                board,
+                line: start,
+                expected: height,
+                found: rows.len(),
             });
         }
+        let cells = rows.into_iter().flat_map(|(_, cells)| cells).collect();
+        grids.push(Grid::from_vec(cells, cols));
+    }
+
+    Ok((drawing, grids))
+}
+
+// The value that occurs most often, favouring the earliest on a tie.
+fn most_common(values: impl Iterator<Item = usize>) -> usize {
+    let mut counts: Vec<(usize, usize)> = vec![];
+    for v in values {
+        match counts.iter_mut().find(|(value, _)| *value == v) {
+            Some((_, n)) => *n += 1,
+            None => counts.push((v, 1)),
+        }
     }
+    counts
+        .iter()
+        .rev()
+        .max_by_key(|(_, n)| *n)
+        .map(|(value, _)| *value)
+        .unwrap_or(0)
+}
+
+fn parse_number(s: &str, board: Option<usize>, line: usize) -> Result<u32, ParseError> {
+    s.parse().map_err(|_| ParseError::BadNumber {
+        board,
+        line,
+        token: s.to_string(),
+    })
 }
 
-fn is_winner(grid: &Grid<u32>, drawing: &Vec<u32>) -> bool {
-    // Rows
-    for i in 0..grid.rows() {
-        if grid.iter_row(i).all(|cell| drawing.contains(&cell)) {
-            return true;
+// Patterns are given on the command line by name, e.g. `row column diagonal`.
+// `mask=<file>` loads user-defined masks. Defaults to plain rows and columns.
+fn parse_patterns<I: Iterator<Item = String>>(args: I) -> Result<Vec<WinPattern>, String> {
+    let mut patterns = vec![];
+    for arg in args {
+        match arg.as_str() {
+            "row" => patterns.push(WinPattern::Row),
+            "column" => patterns.push(WinPattern::Column),
+            "diagonal" => patterns.push(WinPattern::Diagonal),
+            "corners" => patterns.push(WinPattern::FourCorners),
+            "blackout" => patterns.push(WinPattern::Blackout),
+            "x" => patterns.push(WinPattern::XShape),
+            _ => match arg.strip_prefix("mask=") {
+                Some(path) => patterns.extend(read_masks(path)?),
+                None => return Err(format!("unknown win pattern {:?}", arg)),
+            },
         }
     }
+    if patterns.is_empty() {
+        patterns = vec![WinPattern::Row, WinPattern::Column];
+    }
+    Ok(patterns)
+}
 
-    // Columns
-    for i in 0..grid.cols() {
-        if grid.iter_col(i).all(|cell| drawing.contains(&cell)) {
-            return true;
+// Masks are blank-line-separated blocks: a name line followed by one line per
+// board row, with `X` for cells that must be marked and `.` for the rest.
+fn read_masks(path: &str) -> Result<Vec<WinPattern>, String> {
+    let lines = read_lines(path).map_err(|e| format!("{}: {}", path, e))?;
+    let ls: Vec<String> = lines.map_while(Result::ok).collect();
+    parse_masks(path, &ls)
+}
+
+fn parse_masks(path: &str, ls: &[String]) -> Result<Vec<WinPattern>, String> {
+    let mut masks = vec![];
+    for block in ls.split(|l| l.trim().is_empty()).filter(|b| !b.is_empty()) {
+        let name = block[0].trim().to_string();
+        let rows: Vec<Vec<bool>> = block[1..]
+            .iter()
+            .map(|l| {
+                l.chars()
+                    .filter(|c| !c.is_whitespace())
+                    .map(|c| match c {
+                        'X' | 'x' => Ok(true),
+                        '.' => Ok(false),
+                        _ => Err(format!(
+                            "{}: mask {:?} has invalid cell {:?}",
+                            path, name, c
+                        )),
+                    })
+                    .collect()
+            })
+            .collect::<Result<_, _>>()?;
+        let cols = rows.first().map(|r| r.len()).unwrap_or(0);
+        if cols == 0 || rows.iter().any(|r| r.len() != cols) {
+            return Err(format!("{}: mask {:?} is empty or ragged", path, name));
+        }
+        // Nothing to mark would mean every board wins before the first draw.
+        if !rows.iter().flatten().any(|cell| *cell) {
+            return Err(format!("{}: mask {:?} has no marked cells", path, name));
         }
+        masks.push(WinPattern::Mask(name, Grid::from_vec(rows.concat(), cols)));
     }
+    Ok(masks)
+}
+
+fn mark(grid: &Grid<u32>, drawing: &[u32]) -> Grid<bool> {
+    let marks = grid.iter().map(|cell| drawing.contains(cell)).collect();
+    Grid::from_vec(marks, grid.cols())
+}
 
-    false
+fn winning_pattern<'a>(
+    grid: &Grid<u32>,
+    drawing: &[u32],
+    patterns: &'a [WinPattern],
+) -> Option<&'a WinPattern> {
+    let marks = mark(grid, drawing);
+    patterns.iter().find(|p| p.fires(&marks))
 }
 
 // The output is wrapped in a Result to allow matching on errors
@@ -106,3 +681,165 @@ where
     let file = File::open(filename)?;
     Ok(io::BufReader::new(file).lines())
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn lines(s: &str) -> Vec<String> {
+        s.lines().map(|l| l.to_string()).collect()
+    }
+
+    // Three rows of two columns: taller than it is wide.
+    const TALL: &str = "1,2,3,4,5,6\n\n1 2\n3 4\n5 6\n";
+
+    #[test]
+    fn non_square_boards_win_on_rows_and_columns() {
+        let (drawing, grids) = parse_input(&lines(TALL)).unwrap();
+        let grid = &grids[0];
+        assert_eq!(grid.size(), (3, 2));
+
+        let patterns = [
+            WinPattern::Row,
+            WinPattern::Column,
+            WinPattern::Diagonal,
+            WinPattern::XShape,
+        ];
+        for p in &patterns {
+            p.check_size(3, 2).unwrap();
+        }
+        // The first row completes on the second draw.
+        assert!(winning_pattern(grid, &drawing[..1], &patterns).is_none());
+        assert!(matches!(
+            winning_pattern(grid, &drawing[..2], &patterns),
+            Some(WinPattern::Row)
+        ));
+
+        let turns: HashMap<u32, usize> = [(5, 1), (3, 2), (1, 3)].into_iter().collect();
+        assert_eq!(win_turn(grid, &[WinPattern::Column], &turns), Some(3));
+        assert_eq!(win_turn(grid, &[WinPattern::Diagonal], &turns), None);
+    }
+
+    #[test]
+    fn simulate_keeps_patterns_it_does_not_recognise() {
+        let mut args = lines("50\ntrials=7\ndiagonal\nseed=3");
+        assert!(matches!(
+            simulate_options(&mut args),
+            Ok(Mode::Simulate { trials: 7, seed: 3 })
+        ));
+        assert_eq!(args, ["50", "diagonal"]);
+        assert!(simulate_options(&mut lines("trials=many")).is_err());
+        assert!(simulate_options(&mut lines("trials=0")).is_err());
+    }
+
+    #[test]
+    fn generated_input_round_trips_through_the_parser() {
+        for (rows, cols, lo, hi) in [(5, 5, 0, 99), (3, 2, 7, 12), (1, 4, 0, 3), (4, 6, 95, 130)] {
+            let config = GeneratorConfig {
+                boards: 10,
+                rows,
+                cols,
+                lo,
+                hi,
+                seed: 42,
+            };
+            let contents = generate(&config).unwrap();
+            let (drawing, grids) = parse_input(&lines(&contents)).unwrap();
+            assert_eq!(format_input(&drawing, &grids), contents);
+            assert_eq!(drawing.len() as u32, hi - lo + 1);
+            assert_eq!(grids.len(), 10);
+            assert!(grids.iter().all(|g| g.size() == (rows, cols)));
+        }
+    }
+
+    #[test]
+    fn generate_rejects_bad_arguments() {
+        assert!(generator_options(&lines("10\n5\n5\n0\n4294967296")).is_err());
+        assert!(generator_options(&lines("ten")).is_err());
+        assert!(generate(&generator_options(&lines("1\n5\n5\n0\n10")).unwrap()).is_err());
+        assert!(generate(&generator_options(&lines("1\n2\n2\n9\n3")).unwrap()).is_err());
+    }
+
+    // Two full 2x2 boards and one problem board, at lines 3, 6 and 9.
+    fn boards(first: &str, second: &str, third: &str) -> Vec<String> {
+        lines(&format!(
+            "1,2,3,4\n\n{}\n\n{}\n\n{}\n",
+            first, second, third
+        ))
+    }
+
+    #[test]
+    fn malformed_boards_name_the_board_and_line() {
+        let full = "1 2\n3 4";
+        assert_eq!(
+            parse_input(&boards(full, "1 2\n3", full)).unwrap_err(),
+            ParseError::RaggedRow {
+                board: 2,
+                line: 7,
+                expected: 2,
+                found: 1
+            }
+        );
+        // A short trailing board is reported rather than dropped.
+        assert_eq!(
+            parse_input(&boards(full, full, "1 2")).unwrap_err(),
+            ParseError::BoardHeight {
+                board: 3,
+                line: 9,
+                expected: 2,
+                found: 1
+            }
+        );
+        // A short first board is the odd one out, not every board after it.
+        assert_eq!(
+            parse_input(&boards("1 2", full, full)).unwrap_err(),
+            ParseError::BoardHeight {
+                board: 1,
+                line: 3,
+                expected: 2,
+                found: 1
+            }
+        );
+        assert_eq!(
+            parse_input(&boards(full, full, "1 2\n3 x")).unwrap_err(),
+            ParseError::BadNumber {
+                board: Some(3),
+                line: 10,
+                token: "x".to_string()
+            }
+        );
+        assert_eq!(
+            parse_input(&lines("1,x\n\n1")).unwrap_err(),
+            ParseError::BadNumber {
+                board: None,
+                line: 1,
+                token: "x".to_string()
+            }
+        );
+    }
+
+    #[test]
+    fn input_needs_a_drawing_and_boards() {
+        assert_eq!(parse_input(&[]).unwrap_err(), ParseError::MissingDrawing);
+        assert_eq!(
+            parse_input(&lines("1,2,3\n\n")).unwrap_err(),
+            ParseError::NoBoards
+        );
+    }
+
+    #[test]
+    fn masks_need_marked_cells() {
+        let err = parse_masks("test", &lines("dot\n. .\n. X\n\nnone\n. .\n. .")).unwrap_err();
+        assert_eq!(err, "test: mask \"none\" has no marked cells");
+        let masks = parse_masks("test", &lines("dot\n. .\n. X")).unwrap();
+        assert!(matches!(&masks[..], [WinPattern::Mask(name, _)] if name == "dot"));
+        assert!(parse_masks("test", &lines("ragged\nX X\nX")).is_err());
+    }
+
+    #[test]
+    fn masks_must_match_the_board_size() {
+        let mask = WinPattern::Mask("dot".to_string(), Grid::from_vec(vec![true; 4], 2));
+        assert!(mask.check_size(2, 2).is_ok());
+        assert!(mask.check_size(3, 2).is_err());
+    }
+}
@@ -1,30 +1,251 @@
+mod query;
+mod sweep;
+
 use grid::Grid;
 use regex::Regex;
 use std::cmp::max;
-use std::cmp::min;
+use std::collections::{BTreeMap, HashMap};
+use std::env;
 use std::fs::File;
-use std::io::{self, BufRead};
+use std::io::{self, BufRead, BufWriter, Write};
 use std::path::Path;
+use std::process;
+
+type Segment = ((u64, u64), (u64, u64));
+
+#[derive(Debug, PartialEq)]
+enum Part {
+    // Horizontal & vertical vents only.
+    One,
+    // Also 45° diagonals.
+    Two,
+}
+
+// How to turn segments into cells.
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Raster {
+    // Horizontal, vertical and 45° only; anything else is rejected.
+    Strict,
+    // Any slope, drawn with Bresenham's line algorithm.
+    Bresenham,
+    // Any slope, but only the cells the line passes through exactly.
+    Lattice,
+}
+
+impl Raster {
+    fn points(&self, seg: &Segment) -> Box<dyn Iterator<Item = (u64, u64)>> {
+        match self {
+            Raster::Strict => Box::new(segment_points(seg)),
+            Raster::Bresenham => Box::new(bresenham_points(seg)),
+            Raster::Lattice => Box::new(lattice_points(seg)),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord)]
+enum SegmentType {
+    Horizontal,
+    Vertical,
+    Diagonal,
+    // Any other slope.
+    Oblique,
+}
+
+fn segment_type(seg: &Segment) -> SegmentType {
+    let ((x1, y1), (x2, y2)) = seg;
+    if y1 == y2 {
+        SegmentType::Horizontal
+    } else if x1 == x2 {
+        SegmentType::Vertical
+    } else if is_diagonal(seg) {
+        SegmentType::Diagonal
+    } else {
+        SegmentType::Oblique
+    }
+}
+
+#[derive(Debug, PartialEq)]
+enum Storage {
+    // Dense unless the bounding box is bigger than `MAX_DENSE_CELLS`.
+    Auto,
+    Dense,
+    Sparse,
+    // No per-cell storage at all, see `sweep`.
+    Analytic,
+}
+
+const MAX_DENSE_CELLS: u64 = 1 << 24;
+// Coordinates must be below this, so that they fit in an i64 with room for
+// differences and the sweep's `x + y`.
+const MAX_COORD: u64 = 1 << 62;
+// Largest heatmap we'll write, in pixels (48 MiB of PPM).
+const MAX_HEATMAP_PIXELS: u64 = 1 << 24;
+
+// Counts how many vents cover each cell.
+trait OverlapMap {
+    fn add(&mut self, x: u64, y: u64);
+    fn get(&self, x: u64, y: u64) -> u64;
+    // Every cell with a non-zero count.
+    fn covered(&self) -> Box<dyn Iterator<Item = ((u64, u64), u64)> + '_>;
+
+    fn overlaps(&self) -> usize {
+        self.covered().filter(|(_, c)| *c > 1).count()
+    }
+}
+
+impl OverlapMap for Grid<u64> {
+    fn add(&mut self, x: u64, y: u64) {
+        *self.get_mut(y as usize, x as usize).unwrap() += 1;
+    }
+
+    fn get(&self, x: u64, y: u64) -> u64 {
+        Grid::get(self, y as usize, x as usize)
+            .copied()
+            .unwrap_or(0)
+    }
+
+    fn covered(&self) -> Box<dyn Iterator<Item = ((u64, u64), u64)> + '_> {
+        let cols = self.cols();
+        Box::new(
+            self.iter()
+                .enumerate()
+                .filter(|(_, c)| **c > 0)
+                .map(move |(i, c)| (((i % cols) as u64, (i / cols) as u64), *c)),
+        )
+    }
+}
+
+// Only stores covered cells, so memory scales with the length of the vents
+// rather than the size of the bounding box.
+#[derive(Debug, Default)]
+struct SparseGrid {
+    counts: HashMap<(u64, u64), u64>,
+}
+
+impl OverlapMap for SparseGrid {
+    fn add(&mut self, x: u64, y: u64) {
+        *self.counts.entry((x, y)).or_insert(0) += 1;
+    }
+
+    fn get(&self, x: u64, y: u64) -> u64 {
+        self.counts.get(&(x, y)).copied().unwrap_or(0)
+    }
+
+    fn covered(&self) -> Box<dyn Iterator<Item = ((u64, u64), u64)> + '_> {
+        Box::new(self.counts.iter().map(|(p, c)| (*p, *c)))
+    }
+}
+
+// Inclusive rectangle of cells.
+#[derive(Debug, Clone, Copy)]
+struct Window {
+    x0: u64,
+    y0: u64,
+    x1: u64,
+    y1: u64,
+}
+
+impl Window {
+    fn parse(s: &str) -> Option<Window> {
+        let v: Vec<u64> = s
+            .split(',')
+            .map(|n| n.trim().parse().ok())
+            .collect::<Option<_>>()?;
+        match v[..] {
+            [x0, y0, x1, y1] if x0 <= x1 && y0 <= y1 => Some(Window { x0, y0, x1, y1 }),
+            _ => None,
+        }
+    }
+
+    fn width(&self) -> u64 {
+        self.x1 - self.x0 + 1
+    }
+
+    fn height(&self) -> u64 {
+        self.y1 - self.y0 + 1
+    }
+}
 
 fn main() {
-    let re = Regex::new(r"(\d+),(\d+) -> (\d+),(\d+)").unwrap();
+    let args: Vec<String> = env::args().skip(1).collect();
+    if args.first().map(|a| a.as_str()) == Some("query") {
+        if let Err(e) = run_query(&args[1..]) {
+            eprintln!("{}", e);
+            process::exit(1);
+        }
+        return;
+    }
+
+    let mut part = Part::Two;
+    let mut heatmap: Option<String> = None;
+    let mut crop: Option<Window> = None;
+    let mut storage = Storage::Auto;
+    let mut raster = Raster::Strict;
+    for arg in args {
+        if let Some(path) = arg.strip_prefix("heatmap=") {
+            heatmap = Some(path.to_string());
+            continue;
+        }
+        if let Some(window) = arg.strip_prefix("crop=") {
+            crop = Window::parse(window);
+            if crop.is_none() {
+                eprintln!("bad crop window {:?}, expected x0,y0,x1,y1", window);
+                process::exit(1);
+            }
+            continue;
+        }
+        match arg.as_str() {
+            "1" => part = Part::One,
+            "2" => part = Part::Two,
+            "dense" => storage = Storage::Dense,
+            "sparse" => storage = Storage::Sparse,
+            "analytic" => storage = Storage::Analytic,
+            "bresenham" => raster = Raster::Bresenham,
+            "lattice" => raster = Raster::Lattice,
+            other => {
+                eprintln!(
+                    "unknown argument {:?}, expected 1, 2, dense, sparse, analytic, bresenham, lattice, heatmap=<file> or crop=x0,y0,x1,y1",
+                    other
+                );
+                process::exit(1);
+            }
+        }
+    }
 
     if let Ok(lines) = read_lines("./day5/input.txt") {
-        let ls: Vec<String> = lines.filter_map(|line| line.ok()).collect();
+        let ls: Vec<String> = lines.map_while(Result::ok).collect();
 
-        let coords: Vec<_> = ls
-            .iter()
-            .map(|l| {
-                let caps = re.captures(l).unwrap();
-                let x1: u64 = caps.get(1).unwrap().as_str().parse().unwrap();
-                let y1: u64 = caps.get(2).unwrap().as_str().parse().unwrap();
-                let x2: u64 = caps.get(3).unwrap().as_str().parse().unwrap();
-                let y2: u64 = caps.get(4).unwrap().as_str().parse().unwrap();
-                ((x1, y1), (x2, y2))
-            })
-            .collect();
+        let coords = match parse_segments(&ls) {
+            Ok(coords) => coords,
+            Err(e) => {
+                eprintln!("{}", e);
+                process::exit(1);
+            }
+        };
         println!("{:?}", coords);
 
+        // The sweep only understands horizontal, vertical and 45° lines.
+        let strict = raster == Raster::Strict || storage == Storage::Analytic;
+        if strict {
+            if let Err(e) = check_slopes(&coords) {
+                eprintln!("{}", e);
+                process::exit(1);
+            }
+        }
+
+        let mut types: BTreeMap<SegmentType, usize> = BTreeMap::new();
+        coords
+            .iter()
+            .for_each(|seg| *types.entry(segment_type(seg)).or_insert(0) += 1);
+        println!(
+            "segments: {}",
+            types
+                .iter()
+                .map(|(t, n)| format!("{:?} {}", t, n))
+                .collect::<Vec<_>>()
+                .join(", ")
+        );
+
         let grid_size = coords
             .iter()
             .fold((0, 0), |(max_x, max_y), ((x1, y1), (x2, y2))| {
@@ -33,60 +254,329 @@ fn main() {
 
         println!("{:?}", grid_size);
 
-        let mut grid: Grid<u64> = Grid::new((grid_size.1 + 1) as usize, (grid_size.0 + 1) as usize);
+        let cells = (grid_size.0 + 1).saturating_mul(grid_size.1 + 1);
+        let sparse = match storage {
+            Storage::Auto => cells > MAX_DENSE_CELLS,
+            Storage::Dense => false,
+            Storage::Sparse | Storage::Analytic => true,
+        };
 
-        for ((x1, y1), (x2, y2)) in coords {
-            // Only horizontal & vertical for now.
-            if x1 == x2 {
-                let start = min(y1, y2);
-                let end = max(y1, y2);
-                for y in start..=end {
-                    *grid.get_mut(y as usize, x1 as usize).unwrap() += 1;
-                }
-            } else if y1 == y2 {
-                let start = min(x1, x2);
-                let end = max(x1, x2);
-                for x in start..=end {
-                    *grid.get_mut(y1 as usize, x as usize).unwrap() += 1;
-                }
+        let segments = coords
+            .iter()
+            .filter(|seg| part == Part::Two || is_axis_aligned(seg));
+
+        let scary = if storage == Storage::Analytic {
+            if heatmap.is_some() {
+                eprintln!("the analytic engine has no cells to draw a heatmap from");
+            }
+            sweep::count_overlaps(segments) as usize
+        } else {
+            let mut map: Box<dyn OverlapMap> = if sparse {
+                Box::new(SparseGrid::default())
             } else {
-                let start_coord = *vec![(x1, y1), (x2, y2)]
-                    .iter()
-                    .min_by(|(x1, _y1), (x2, _y2)| x1.cmp(x2))
-                    .unwrap();
+                Box::new(Grid::<u64>::new(
+                    (grid_size.1 + 1) as usize,
+                    (grid_size.0 + 1) as usize,
+                ))
+            };
+            draw(map.as_mut(), segments, raster);
 
-                let end_coord = if x1 == start_coord.0 {
-                    (x2, y2)
-                } else {
-                    (x1, y1)
+            // A sparse map's bounding box can be enormous, so by default only
+            // draw the part that anything covers.
+            let window = crop.unwrap_or_else(|| {
+                let full = Window {
+                    x0: 0,
+                    y0: 0,
+                    x1: grid_size.0,
+                    y1: grid_size.1,
                 };
+                if sparse {
+                    covered_window(map.as_ref()).unwrap_or(full)
+                } else {
+                    full
+                }
+            });
+            if !sparse {
+                print_grid(map.as_ref(), window);
+            }
+            if let Some(path) = &heatmap {
+                let pixels = window.width().saturating_mul(window.height());
+                if pixels > MAX_HEATMAP_PIXELS {
+                    eprintln!(
+                        "a {}x{} heatmap is too big to write, pick a smaller area with crop=x0,y0,x1,y1",
+                        window.width(),
+                        window.height()
+                    );
+                } else if let Err(e) = write_heatmap(map.as_ref(), window, path) {
+                    eprintln!("{}: {}", path, e);
+                } else {
+                    println!(
+                        "wrote {}x{} heatmap to {}",
+                        window.width(),
+                        window.height(),
+                        path
+                    );
+                }
+            }
+            map.overlaps()
+        };
+
+        println!("{}", scary);
+    }
+}
+
+fn parse_segments(ls: &[String]) -> Result<Vec<Segment>, String> {
+    let re = Regex::new(r"(\d+),(\d+) -> (\d+),(\d+)").unwrap();
+    ls.iter()
+        .enumerate()
+        .map(|(i, l)| {
+            let caps = re
+                .captures(l)
+                .ok_or_else(|| format!("line {}: expected x1,y1 -> x2,y2, got {:?}", i + 1, l))?;
+            let num = |n: usize| -> Result<u64, String> {
+                let s = caps.get(n).unwrap().as_str();
+                s.parse()
+                    .ok()
+                    .filter(|c| *c < MAX_COORD)
+                    .ok_or_else(|| format!("line {}: {} is out of range", i + 1, s))
+            };
+            Ok(((num(1)?, num(2)?), (num(3)?, num(4)?)))
+        })
+        .collect()
+}
+
+// Unless a raster mode for arbitrary slopes was asked for, anything that
+// isn't axis-aligned has to be exactly 45°, otherwise stepping one cell at a
+// time along it would draw the wrong cells.
+fn check_slopes(coords: &[Segment]) -> Result<(), String> {
+    match coords
+        .iter()
+        .position(|seg| segment_type(seg) == SegmentType::Oblique)
+    {
+        Some(i) => Err(format!(
+            "line {}: {:?} is not horizontal, vertical or 45° diagonal",
+            i + 1,
+            coords[i]
+        )),
+        None => Ok(()),
+    }
+}
+
+// `query <file> point x,y`, `query <file> segments x,y` or
+// `query <file> region x0,y0,x1,y1 k`, optionally followed by a raster mode.
+fn run_query(args: &[String]) -> Result<(), String> {
+    let usage =
+        "usage: query <file> (point x,y | segments x,y | region x0,y0,x1,y1 k) [bresenham|lattice]";
+    let path = args.first().ok_or(usage)?;
+    let kind = args.get(1).ok_or(usage)?;
+
+    let mut raster = Raster::Strict;
+    let mut rest = vec![];
+    for arg in &args[2..] {
+        match arg.as_str() {
+            "bresenham" => raster = Raster::Bresenham,
+            "lattice" => raster = Raster::Lattice,
+            _ => rest.push(arg.as_str()),
+        }
+    }
+
+    let lines = read_lines(path).map_err(|e| format!("{}: {}", path, e))?;
+    let ls: Vec<String> = lines.map_while(Result::ok).collect();
+    let coords = parse_segments(&ls)?;
+    if raster == Raster::Strict {
+        check_slopes(&coords)?;
+    }
+    let vents = query::VentLines::new(coords, raster);
 
-                let y_increment = if start_coord.1 < end_coord.1 { 1 } else { -1 } as i64;
+    let point = |s: Option<&&str>| -> Result<(u64, u64), String> {
+        let s = s.ok_or(usage)?;
+        s.split_once(',')
+            .and_then(|(x, y)| Some((x.trim().parse().ok()?, y.trim().parse().ok()?)))
+            .ok_or_else(|| format!("bad point {:?}, expected x,y", s))
+    };
 
-                (start_coord.0..=end_coord.0)
-                    .enumerate()
-                    .for_each(|(i, x)| {
-                        let delta_y = i as i64 * y_increment;
-                        let next_y: u64 = (start_coord.1 as i64 + delta_y) as u64;
-                        *grid.get_mut(next_y as usize, x as usize).unwrap() += 1;
-                    })
+    match kind.as_str() {
+        "point" => {
+            let (x, y) = point(rest.first())?;
+            println!("{}", vents.coverage(x, y));
+        }
+        "segments" => {
+            let (x, y) = point(rest.first())?;
+            for (i, ((x1, y1), (x2, y2))) in vents.covering(x, y) {
+                println!("line {}: {},{} -> {},{}", i + 1, x1, y1, x2, y2);
+            }
+        }
+        "region" => {
+            let window = rest
+                .first()
+                .and_then(|w| Window::parse(w))
+                .ok_or("bad region, expected x0,y0,x1,y1")?;
+            let k: u64 = match rest.get(1) {
+                Some(k) => k.parse().map_err(|_| format!("bad threshold {:?}", k))?,
+                None => 2,
+            };
+            for ((x, y), count) in vents.hotspots(window, k) {
+                println!("{},{} {}", x, y, count);
             }
         }
+        _ => return Err(usage.to_string()),
+    }
+    Ok(())
+}
 
-        print_grid(&grid);
+fn draw<'a>(map: &mut dyn OverlapMap, segments: impl Iterator<Item = &'a Segment>, raster: Raster) {
+    for seg in segments {
+        for (x, y) in raster.points(seg) {
+            map.add(x, y);
+        }
+    }
+}
 
-        let scary = grid.iter().filter(|c| *c > &1).count();
-        println!("{}", scary);
+fn is_axis_aligned(((x1, y1), (x2, y2)): &Segment) -> bool {
+    x1 == x2 || y1 == y2
+}
+
+fn is_diagonal(((x1, y1), (x2, y2)): &Segment) -> bool {
+    x1.abs_diff(*x2) == y1.abs_diff(*y2)
+}
+
+// Every cell covered by a horizontal, vertical or 45° segment, inclusive of
+// both ends.
+fn segment_points(((x1, y1), (x2, y2)): &Segment) -> impl Iterator<Item = (u64, u64)> {
+    let step = |a: u64, b: u64| (b as i64 - a as i64).signum();
+    let (dx, dy) = (step(*x1, *x2), step(*y1, *y2));
+    let len = max(x1.abs_diff(*x2), y1.abs_diff(*y2)) as i64;
+    let (x1, y1) = (*x1 as i64, *y1 as i64);
+    (0..=len).map(move |i| ((x1 + i * dx) as u64, (y1 + i * dy) as u64))
+}
+
+// Bresenham's line algorithm, generalised to all octants.
+fn bresenham_points(((x1, y1), (x2, y2)): &Segment) -> impl Iterator<Item = (u64, u64)> {
+    let (x2, y2) = (*x2 as i64, *y2 as i64);
+    let (mut x, mut y) = (*x1 as i64, *y1 as i64);
+    let (dx, sx) = ((x2 - x).abs(), (x2 - x).signum());
+    let (dy, sy) = (-(y2 - y).abs(), (y2 - y).signum());
+    let mut err = dx + dy;
+    let mut done = false;
+    std::iter::from_fn(move || {
+        if done {
+            return None;
+        }
+        let p = (x as u64, y as u64);
+        if x == x2 && y == y2 {
+            done = true;
+        } else {
+            let e2 = 2 * err;
+            if e2 >= dy {
+                err += dy;
+                x += sx;
+            }
+            if e2 <= dx {
+                err += dx;
+                y += sy;
+            }
+        }
+        Some(p)
+    })
+}
+
+// Only the integer points that lie exactly on the line, i.e. steps of
+// (dx, dy) / gcd(dx, dy).
+fn lattice_points(((x1, y1), (x2, y2)): &Segment) -> impl Iterator<Item = (u64, u64)> {
+    let (dx, dy) = (*x2 as i64 - *x1 as i64, *y2 as i64 - *y1 as i64);
+    let steps = gcd(dx.unsigned_abs(), dy.unsigned_abs()) as i64;
+    let (sx, sy) = if steps == 0 {
+        (0, 0)
+    } else {
+        (dx / steps, dy / steps)
+    };
+    let (x1, y1) = (*x1 as i64, *y1 as i64);
+    (0..=steps).map(move |i| ((x1 + i * sx) as u64, (y1 + i * sy) as u64))
+}
+
+fn gcd(a: u64, b: u64) -> u64 {
+    if b == 0 {
+        a
+    } else {
+        gcd(b, a % b)
     }
 }
 
-fn print_grid(grid: &Grid<u64>) {
-    for row in 0..grid.rows() {
-        grid.iter_row(row).for_each(|c| print!("{}", c));
+fn print_grid(map: &dyn OverlapMap, window: Window) {
+    for y in window.y0..=window.y1 {
+        (window.x0..=window.x1).for_each(|x| print!("{}", map.get(x, y)));
         println!();
     }
 }
 
+// The smallest window holding every covered cell.
+fn covered_window(map: &dyn OverlapMap) -> Option<Window> {
+    map.covered().fold(None, |window, ((x, y), _)| {
+        Some(match window {
+            None => Window {
+                x0: x,
+                y0: y,
+                x1: x,
+                y1: y,
+            },
+            Some(w) => Window {
+                x0: w.x0.min(x),
+                y0: w.y0.min(y),
+                x1: w.x1.max(x),
+                y1: w.y1.max(y),
+            },
+        })
+    })
+}
+
+// Writes the window as a binary PPM, black for uncovered cells and running
+// from blue through green and yellow to red as the count approaches the
+// highest count in the window.
+fn write_heatmap(map: &dyn OverlapMap, window: Window, path: &str) -> io::Result<()> {
+    let max_count = map
+        .covered()
+        .filter(|((x, y), _)| {
+            (window.x0..=window.x1).contains(x) && (window.y0..=window.y1).contains(y)
+        })
+        .map(|(_, c)| c)
+        .max()
+        .unwrap_or(0);
+
+    let mut out = BufWriter::new(File::create(path)?);
+    write!(out, "P6\n{} {}\n255\n", window.width(), window.height())?;
+    for y in window.y0..=window.y1 {
+        for x in window.x0..=window.x1 {
+            out.write_all(&heat_colour(map.get(x, y), max_count))?;
+        }
+    }
+    out.flush()
+}
+
+fn heat_colour(count: u64, max_count: u64) -> [u8; 3] {
+    const RAMP: [[f64; 3]; 5] = [
+        [0.0, 0.0, 255.0],
+        [0.0, 255.0, 255.0],
+        [0.0, 255.0, 0.0],
+        [255.0, 255.0, 0.0],
+        [255.0, 0.0, 0.0],
+    ];
+    if count == 0 {
+        return [0, 0, 0];
+    }
+    if max_count <= 1 {
+        return [0, 0, 255];
+    }
+
+    let t = (count - 1) as f64 / (max_count - 1) as f64 * (RAMP.len() - 1) as f64;
+    let i = (t.floor() as usize).min(RAMP.len() - 2);
+    let f = t - i as f64;
+    let mut rgb = [0; 3];
+    for (c, out) in rgb.iter_mut().enumerate() {
+        *out = (RAMP[i][c] + (RAMP[i + 1][c] - RAMP[i][c]) * f).round() as u8;
+    }
+    rgb
+}
+
 // The output is wrapped in a Result to allow matching on errors
 // Returns an Iterator to the Reader of the lines of the file.
 fn read_lines<P>(filename: P) -> io::Result<io::Lines<io::BufReader<File>>>
@@ -96,3 +586,225 @@ where
     let file = File::open(filename)?;
     Ok(io::BufReader::new(file).lines())
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rand::rngs::StdRng;
+    use rand::{Rng, SeedableRng};
+
+    pub(crate) fn example() -> Vec<Segment> {
+        let ls: Vec<String> = include_str!("example.txt")
+            .lines()
+            .map(|l| l.to_string())
+            .collect();
+        parse_segments(&ls).unwrap()
+    }
+
+    fn rasterised_overlaps<'a>(segments: impl Iterator<Item = &'a Segment>) -> u64 {
+        let mut map = SparseGrid::default();
+        draw(&mut map, segments, Raster::Strict);
+        map.overlaps() as u64
+    }
+
+    // `n` horizontal, vertical or 45° segments with both ends inside
+    // `0..extent` on each axis.
+    pub(crate) fn random_segments(rng: &mut StdRng, n: usize, extent: u64) -> Vec<Segment> {
+        (0..n)
+            .map(|_| {
+                let (x1, y1) = (rng.gen_range(0..extent), rng.gen_range(0..extent));
+                let (dx, dy) = [(1, 0), (0, 1), (1, 1), (1, -1)][rng.gen_range(0..4)];
+                let room = |p: u64, d: i64| match d {
+                    1 => extent - 1 - p,
+                    -1 => p,
+                    _ => extent,
+                };
+                let len = rng.gen_range(0..extent).min(room(x1, dx)).min(room(y1, dy));
+                let x2 = (x1 as i64 + dx * len as i64) as u64;
+                let y2 = (y1 as i64 + dy * len as i64) as u64;
+                if rng.gen() {
+                    ((x1, y1), (x2, y2))
+                } else {
+                    ((x2, y2), (x1, y1))
+                }
+            })
+            .collect()
+    }
+
+    #[test]
+    fn coordinates_are_bounded() {
+        let parse = |l: &str| parse_segments(&[l.to_string()]);
+        assert!(parse("0,0 -> 18446744073709551615,0").is_err());
+        assert!(parse("0,0 -> 99999999999999999999,0").is_err());
+        assert!(parse("0,4611686018427387904 -> 0,0").is_err());
+        assert_eq!(
+            parse("4611686018427387903,0 -> 0,4611686018427387903"),
+            Ok(vec![((MAX_COORD - 1, 0), (0, MAX_COORD - 1))])
+        );
+    }
+
+    // Every octant, from a start far enough from the edge that no end goes
+    // negative.
+    fn octant_segments() -> Vec<Segment> {
+        let mut segments = vec![];
+        for (a, b) in [(7, 3), (3, 7)] {
+            for (sx, sy) in [(1, 1), (1, -1), (-1, 1), (-1, -1)] {
+                let end = ((10 + sx * a) as u64, (10 + sy * b) as u64);
+                segments.push(((10, 10), end));
+            }
+        }
+        segments
+    }
+
+    #[test]
+    fn bresenham_steps_along_the_line_in_every_octant() {
+        for seg in octant_segments() {
+            for seg in [seg, (seg.1, seg.0)] {
+                let ((x1, y1), (x2, y2)) = seg;
+                let points: Vec<_> = bresenham_points(&seg).collect();
+                let (dx, dy) = (x2 as i64 - x1 as i64, y2 as i64 - y1 as i64);
+                let major = dx.abs().max(dy.abs());
+                assert_eq!(points.first(), Some(&(x1, y1)), "{:?}", seg);
+                assert_eq!(points.last(), Some(&(x2, y2)), "{:?}", seg);
+                assert_eq!(points.len() as i64, major + 1, "{:?}", seg);
+                for w in points.windows(2) {
+                    let step = (w[1].0.abs_diff(w[0].0), w[1].1.abs_diff(w[0].1));
+                    assert!(step.0 <= 1 && step.1 <= 1 && step != (0, 0), "{:?}", seg);
+                }
+                // Within half a cell of the true line along the minor axis.
+                for (x, y) in &points {
+                    let (px, py) = (*x as i64 - x1 as i64, *y as i64 - y1 as i64);
+                    assert!(2 * (dx * py - dy * px).abs() <= major, "{:?}", seg);
+                }
+            }
+        }
+        assert_eq!(
+            bresenham_points(&((0, 0), (5, 2))).collect::<Vec<_>>(),
+            vec![(0, 0), (1, 0), (2, 1), (3, 1), (4, 2), (5, 2)]
+        );
+    }
+
+    #[test]
+    fn lattice_points_step_by_the_gcd() {
+        let points = |seg: Segment| lattice_points(&seg).collect::<Vec<_>>();
+        assert_eq!(points(((0, 0), (6, 4))), vec![(0, 0), (3, 2), (6, 4)]);
+        assert_eq!(points(((6, 4), (0, 0))), vec![(6, 4), (3, 2), (0, 0)]);
+        assert_eq!(points(((0, 0), (3, 5))), vec![(0, 0), (3, 5)]);
+        assert_eq!(
+            points(((2, 9), (2, 6))),
+            vec![(2, 9), (2, 8), (2, 7), (2, 6)]
+        );
+        assert_eq!(
+            points(((6, 0), (0, 9))),
+            vec![(6, 0), (4, 3), (2, 6), (0, 9)]
+        );
+        for seg in octant_segments() {
+            assert_eq!(points(seg).len(), 2, "{:?}", seg);
+        }
+    }
+
+    #[test]
+    fn zero_length_segments_are_one_point() {
+        for raster in [Raster::Strict, Raster::Bresenham, Raster::Lattice] {
+            assert_eq!(
+                raster.points(&((4, 5), (4, 5))).collect::<Vec<_>>(),
+                vec![(4, 5)],
+                "{:?}",
+                raster
+            );
+        }
+    }
+
+    #[test]
+    fn example_overlaps() {
+        let segments = example();
+        let axis_aligned = || segments.iter().filter(|seg| is_axis_aligned(seg));
+        assert_eq!(rasterised_overlaps(axis_aligned()), 5);
+        assert_eq!(sweep::count_overlaps(axis_aligned()), 5);
+        assert_eq!(rasterised_overlaps(segments.iter()), 12);
+        assert_eq!(sweep::count_overlaps(segments.iter()), 12);
+    }
+
+    #[test]
+    fn sparse_windows_cover_only_drawn_cells() {
+        let mut map = SparseGrid::default();
+        let segments = [
+            ((3_000_000, 7), (3_000_004, 7)),
+            ((3_000_002, 5), (3_000_002, 9)),
+        ];
+        draw(&mut map, segments.iter(), Raster::Strict);
+        let w = covered_window(&map).unwrap();
+        assert_eq!((w.x0, w.y0, w.x1, w.y1), (3_000_000, 5, 3_000_004, 9));
+        assert!(covered_window(&SparseGrid::default()).is_none());
+    }
+
+    #[test]
+    fn heat_colours_run_from_blue_to_red() {
+        assert_eq!(heat_colour(0, 0), [0, 0, 0]);
+        assert_eq!(heat_colour(0, 9), [0, 0, 0]);
+        assert_eq!(heat_colour(1, 1), [0, 0, 255]);
+        assert_eq!(heat_colour(1, 9), [0, 0, 255]);
+        assert_eq!(heat_colour(5, 9), [0, 255, 0]);
+        assert_eq!(heat_colour(9, 9), [255, 0, 0]);
+    }
+
+    #[test]
+    fn heatmaps_are_the_size_of_the_window() {
+        let mut map = SparseGrid::default();
+        draw(&mut map, example().iter(), Raster::Strict);
+        let window = Window {
+            x0: 2,
+            y0: 3,
+            x1: 8,
+            y1: 5,
+        };
+        let path = std::env::temp_dir().join(format!("day5-heatmap-{}.ppm", std::process::id()));
+        write_heatmap(&map, window, path.to_str().unwrap()).unwrap();
+        let ppm = std::fs::read(&path).unwrap();
+        std::fs::remove_file(&path).unwrap();
+
+        let header = b"P6\n7 3\n255\n";
+        assert_eq!(&ppm[..header.len()], header);
+        assert_eq!(ppm.len(), header.len() + 7 * 3 * 3);
+        // The top left pixel is (2, 3), scaled to the highest count inside
+        // the window rather than across the whole map.
+        let max = (3..=5)
+            .flat_map(|y| (2..=8).map(move |x| (x, y)))
+            .map(|(x, y)| map.get(x, y))
+            .max()
+            .unwrap();
+        assert_eq!(
+            ppm[header.len()..header.len() + 3],
+            heat_colour(map.get(2, 3), max)
+        );
+    }
+
+    #[test]
+    fn sweep_matches_rasteriser_on_crowded_segments() {
+        let mut rng = StdRng::seed_from_u64(5);
+        for _ in 0..500 {
+            let n = rng.gen_range(1..40);
+            let segments = random_segments(&mut rng, n, 40);
+            assert_eq!(
+                sweep::count_overlaps(segments.iter()),
+                rasterised_overlaps(segments.iter()),
+                "{:?}",
+                segments
+            );
+        }
+    }
+
+    #[test]
+    fn sweep_matches_rasteriser_on_long_segments() {
+        let mut rng = StdRng::seed_from_u64(5);
+        for _ in 0..20 {
+            let segments = random_segments(&mut rng, 5, 100_000);
+            assert_eq!(
+                sweep::count_overlaps(segments.iter()),
+                rasterised_overlaps(segments.iter()),
+                "{:?}",
+                segments
+            );
+        }
+    }
+}
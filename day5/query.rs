@@ -0,0 +1,175 @@
+// Point and region queries over a set of vent lines, answered straight from
+// the segments rather than a pre-built overlap map.
+
+use std::collections::HashMap;
+
+use crate::{gcd, Raster, Segment, Window};
+
+pub struct VentLines {
+    segments: Vec<Segment>,
+    raster: Raster,
+}
+
+impl VentLines {
+    pub fn new(segments: Vec<Segment>, raster: Raster) -> VentLines {
+        VentLines { segments, raster }
+    }
+
+    // How many lines cover (x, y).
+    pub fn coverage(&self, x: u64, y: u64) -> usize {
+        self.covering(x, y).len()
+    }
+
+    // The lines covering (x, y), with their index in the input.
+    pub fn covering(&self, x: u64, y: u64) -> Vec<(usize, &Segment)> {
+        self.segments
+            .iter()
+            .enumerate()
+            .filter(|(_, seg)| self.covers(seg, (x, y)))
+            .collect()
+    }
+
+    // Every cell in the window covered by at least `k` lines, ordered by row
+    // then column.
+    pub fn hotspots(&self, window: Window, k: u64) -> Vec<((u64, u64), u64)> {
+        let in_window = |(x, y): (u64, u64)| {
+            (window.x0..=window.x1).contains(&x) && (window.y0..=window.y1).contains(&y)
+        };
+
+        let mut counts: HashMap<(u64, u64), u64> = HashMap::new();
+        for seg in self
+            .segments
+            .iter()
+            .filter(|seg| overlaps_window(seg, window))
+        {
+            for p in self.raster.points(seg).filter(|p| in_window(*p)) {
+                *counts.entry(p).or_insert(0) += 1;
+            }
+        }
+
+        let mut out: Vec<_> = counts.into_iter().filter(|(_, c)| *c >= k).collect();
+        out.sort_by_key(|((x, y), _)| (*y, *x));
+        out
+    }
+
+    fn covers(&self, seg: &Segment, p: (u64, u64)) -> bool {
+        let ((x1, y1), (x2, y2)) = *seg;
+        let (x, y) = p;
+        if x < x1.min(x2) || x > x1.max(x2) || y < y1.min(y2) || y > y1.max(y2) {
+            return false;
+        }
+        match self.raster {
+            // Bresenham cells can sit off the true line, so walk it.
+            Raster::Bresenham => self.raster.points(seg).any(|q| q == p),
+            // Otherwise the cell must be an exact step along the line.
+            Raster::Strict | Raster::Lattice => {
+                // Coordinates are below 2^62, so the products fit in i128.
+                let (dx, dy) = (x2 as i128 - x1 as i128, y2 as i128 - y1 as i128);
+                let (px, py) = (x as i128 - x1 as i128, y as i128 - y1 as i128);
+                let steps = gcd(dx.unsigned_abs() as u64, dy.unsigned_abs() as u64) as i128;
+                if steps == 0 {
+                    return px == 0 && py == 0;
+                }
+                let (sx, sy) = (dx / steps, dy / steps);
+                dx * py == dy * px && (px * sx + py * sy) % (sx * sx + sy * sy) == 0
+            }
+        }
+    }
+}
+
+fn overlaps_window(((x1, y1), (x2, y2)): &Segment, window: Window) -> bool {
+    x1.max(x2) >= &window.x0
+        && x1.min(x2) <= &window.x1
+        && y1.max(y2) >= &window.y0
+        && y1.min(y2) <= &window.y1
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::tests::{example, random_segments};
+    use crate::{draw, OverlapMap, SparseGrid};
+    use rand::rngs::StdRng;
+    use rand::{Rng, SeedableRng};
+
+    #[test]
+    fn example_queries() {
+        let segments = example();
+        let vents = VentLines::new(segments.clone(), Raster::Strict);
+        assert_eq!(vents.coverage(7, 4), 2);
+        assert_eq!(
+            vents.covering(7, 4),
+            vec![(2, &segments[2]), (4, &segments[4])]
+        );
+        assert_eq!(vents.coverage(0, 1), 0);
+
+        let mut map = SparseGrid::default();
+        draw(&mut map, segments.iter(), Raster::Strict);
+        let whole = Window {
+            x0: 0,
+            y0: 0,
+            x1: 9,
+            y1: 9,
+        };
+        assert_eq!(vents.hotspots(whole, 2).len(), 12);
+        let crop = Window {
+            x0: 3,
+            y0: 2,
+            x1: 7,
+            y1: 5,
+        };
+        let mut expected: Vec<_> = map
+            .covered()
+            .filter(|((x, y), c)| {
+                *c >= 2 && (crop.x0..=crop.x1).contains(x) && (crop.y0..=crop.y1).contains(y)
+            })
+            .collect();
+        expected.sort_by_key(|((x, y), _)| (*y, *x));
+        assert_eq!(vents.hotspots(crop, 2), expected);
+        assert!(vents.hotspots(whole, 4).is_empty());
+    }
+
+    // Every cell in and around the segment's bounding box is covered exactly
+    // when the rasteriser draws it.
+    fn assert_covers_matches_points(raster: Raster, seg: Segment) {
+        let vents = VentLines::new(vec![seg], raster);
+        let drawn: Vec<_> = raster.points(&seg).collect();
+        let ((x1, y1), (x2, y2)) = seg;
+        for y in y1.min(y2).saturating_sub(1)..=y1.max(y2) + 1 {
+            for x in x1.min(x2).saturating_sub(1)..=x1.max(x2) + 1 {
+                assert_eq!(
+                    vents.covers(&seg, (x, y)),
+                    drawn.contains(&(x, y)),
+                    "{:?} {:?} at {:?}",
+                    raster,
+                    seg,
+                    (x, y)
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn covers_agrees_with_the_rasteriser() {
+        let mut rng = StdRng::seed_from_u64(35);
+        for seg in random_segments(&mut rng, 200, 30) {
+            assert_covers_matches_points(Raster::Strict, seg);
+        }
+        for _ in 0..200 {
+            let mut point = || (rng.gen_range(0..30), rng.gen_range(0..30));
+            let seg = (point(), point());
+            assert_covers_matches_points(Raster::Bresenham, seg);
+            assert_covers_matches_points(Raster::Lattice, seg);
+        }
+    }
+
+    #[test]
+    fn covers_handles_the_largest_coordinates() {
+        let k = 1 << 60;
+        let seg = ((0, 0), (3 * k, 2 * k));
+        let vents = VentLines::new(vec![seg], Raster::Lattice);
+        assert_eq!(vents.coverage(3 * k - 3, 2 * k - 2), 1);
+        assert_eq!(vents.coverage(3 * k - 3, 2 * k - 1), 0);
+        assert_eq!(vents.coverage(3 * k, 2 * k), 1);
+    }
+}
@@ -0,0 +1,206 @@
+// Counts overlap points geometrically rather than by visiting every cell, so
+// the cost depends on the number of segments and not on their length.
+//
+// Every segment lies on a line `a*x + b*y = c` from one of four families
+// (horizontal, vertical and the two diagonals). Collinear overlaps are found
+// by sweeping the 1D intervals on each line, and crossings between families
+// by sweeping over x and intersecting segments whose x ranges overlap.
+
+use std::collections::{HashMap, HashSet};
+
+use crate::Segment;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+enum Family {
+    Horizontal,
+    Vertical,
+    // y - x is constant.
+    Diagonal,
+    // y + x is constant.
+    AntiDiagonal,
+}
+
+const FAMILIES: [Family; 4] = [
+    Family::Horizontal,
+    Family::Vertical,
+    Family::Diagonal,
+    Family::AntiDiagonal,
+];
+
+impl Family {
+    // (a, b) such that the line is `a*x + b*y = c`.
+    fn coefficients(&self) -> (i64, i64) {
+        match self {
+            Family::Horizontal => (0, 1),
+            Family::Vertical => (1, 0),
+            Family::Diagonal => (-1, 1),
+            Family::AntiDiagonal => (1, 1),
+        }
+    }
+
+    fn constant(&self, (x, y): (i64, i64)) -> i64 {
+        let (a, b) = self.coefficients();
+        a * x + b * y
+    }
+
+    // Position of a point along a line of this family.
+    fn param(&self, (x, y): (i64, i64)) -> i64 {
+        match self {
+            Family::Vertical => y,
+            _ => x,
+        }
+    }
+}
+
+#[derive(Debug)]
+struct Line {
+    family: Family,
+    c: i64,
+    // Inclusive range of `Family::param` covered by the segment.
+    lo: i64,
+    hi: i64,
+    min_x: i64,
+    max_x: i64,
+}
+
+impl Line {
+    fn new(((x1, y1), (x2, y2)): &Segment) -> Line {
+        let (p1, p2) = ((*x1 as i64, *y1 as i64), (*x2 as i64, *y2 as i64));
+        let family = if y1 == y2 {
+            Family::Horizontal
+        } else if x1 == x2 {
+            Family::Vertical
+        } else if (p2.0 - p1.0).signum() == (p2.1 - p1.1).signum() {
+            Family::Diagonal
+        } else {
+            Family::AntiDiagonal
+        };
+        let (t1, t2) = (family.param(p1), family.param(p2));
+        Line {
+            family,
+            c: family.constant(p1),
+            lo: t1.min(t2),
+            hi: t1.max(t2),
+            min_x: p1.0.min(p2.0),
+            max_x: p1.0.max(p2.0),
+        }
+    }
+
+    fn contains(&self, p: (i64, i64)) -> bool {
+        let t = self.family.param(p);
+        self.family.constant(p) == self.c && self.lo <= t && t <= self.hi
+    }
+
+    // The single lattice point where two non-parallel segments meet, if any.
+    fn crossing(&self, other: &Line) -> Option<(i64, i64)> {
+        let (a1, b1) = self.family.coefficients();
+        let (a2, b2) = other.family.coefficients();
+        let det = a1 * b2 - a2 * b1;
+        if det == 0 {
+            return None;
+        }
+        let x_num = self.c * b2 - other.c * b1;
+        let y_num = a1 * other.c - a2 * self.c;
+        // Diagonals of different parity cross between lattice points.
+        if x_num % det != 0 || y_num % det != 0 {
+            return None;
+        }
+        let p = (x_num / det, y_num / det);
+        if self.contains(p) && other.contains(p) {
+            Some(p)
+        } else {
+            None
+        }
+    }
+}
+
+// Number of points covered by at least two segments. Segments must be
+// horizontal, vertical or 45° diagonals.
+pub fn count_overlaps<'a>(segments: impl Iterator<Item = &'a Segment>) -> u64 {
+    let lines: Vec<Line> = segments.map(Line::new).collect();
+
+    let overlaps = collinear_overlaps(&lines);
+    let collinear: u64 = overlaps
+        .values()
+        .flatten()
+        .map(|(lo, hi)| (hi - lo + 1) as u64)
+        .sum();
+
+    // A crossing point may already be inside a collinear overlap on one or
+    // more of the lines through it; count it exactly once.
+    let mut total = collinear as i64;
+    for p in crossings(&lines) {
+        let already = FAMILIES
+            .iter()
+            .filter(|f| {
+                overlaps
+                    .get(&(**f, f.constant(p)))
+                    .map(|runs| in_runs(runs, f.param(p)))
+                    .unwrap_or(false)
+            })
+            .count() as i64;
+        total += 1 - already;
+    }
+    total as u64
+}
+
+// For every line, the sorted disjoint runs covered by two or more segments.
+fn collinear_overlaps(lines: &[Line]) -> HashMap<(Family, i64), Vec<(i64, i64)>> {
+    let mut events: HashMap<(Family, i64), Vec<(i64, i64)>> = HashMap::new();
+    for l in lines {
+        let e = events.entry((l.family, l.c)).or_default();
+        e.push((l.lo, 1));
+        e.push((l.hi + 1, -1));
+    }
+
+    let mut out = HashMap::new();
+    for (key, mut e) in events {
+        e.sort();
+        let mut runs = vec![];
+        let mut depth = 0;
+        let mut start = None;
+        for (t, delta) in e {
+            depth += delta;
+            match start {
+                None if depth >= 2 => start = Some(t),
+                Some(s) if depth < 2 => {
+                    if t > s {
+                        runs.push((s, t - 1));
+                    }
+                    start = None;
+                }
+                _ => {}
+            }
+        }
+        if !runs.is_empty() {
+            out.insert(key, runs);
+        }
+    }
+    out
+}
+
+fn in_runs(runs: &[(i64, i64)], t: i64) -> bool {
+    let i = runs.partition_point(|(_, hi)| *hi < t);
+    i < runs.len() && runs[i].0 <= t
+}
+
+// Distinct points where segments from different families meet.
+fn crossings(lines: &[Line]) -> HashSet<(i64, i64)> {
+    let mut order: Vec<&Line> = lines.iter().collect();
+    order.sort_by_key(|l| l.min_x);
+
+    let mut out = HashSet::new();
+    let mut active: Vec<&Line> = vec![];
+    for l in order {
+        active.retain(|a| a.max_x >= l.min_x);
+        for a in &active {
+            if a.family != l.family {
+                if let Some(p) = a.crossing(l) {
+                    out.insert(p);
+                }
+            }
+        }
+        active.push(l);
+    }
+    out
+}
@@ -1,38 +1,397 @@
+use num_bigint::BigUint;
+use num_traits::{CheckedAdd, CheckedMul, One, ToPrimitive, Zero};
+use std::env;
+use std::fmt::{Debug, Display};
 use std::fs::File;
-use std::io::{self, BufRead};
+use std::io::{self, BufRead, BufWriter, Write};
 use std::path::Path;
+use std::process;
+
+// An age-bucket population model. Each individual spawns `offspring` new
+// ones every `cycle` days; newborns take an extra `newborn_delay` days before
+// their first spawn. If `mortality` is set, individuals die on reaching that
+// age.
+#[derive(Debug, Clone, PartialEq)]
+struct Lifecycle {
+    cycle: usize,
+    newborn_delay: usize,
+    offspring: u64,
+    mortality: Option<usize>,
+}
+
+impl Lifecycle {
+    fn lanternfish() -> Lifecycle {
+        Lifecycle {
+            cycle: 7,
+            newborn_delay: 2,
+            offspring: 1,
+            mortality: None,
+        }
+    }
+
+    // `key = value` lines, `#` starts a comment. Unset keys keep their
+    // lanternfish values.
+    fn load(path: &str) -> Result<Lifecycle, String> {
+        let lines = read_lines(path).map_err(|e| format!("{}: {}", path, e))?;
+        let mut model = Lifecycle::lanternfish();
+        for (i, l) in lines.map_while(Result::ok).enumerate() {
+            let l = l.split('#').next().unwrap().trim();
+            if l.is_empty() {
+                continue;
+            }
+            let err = || format!("{}:{}: expected key = value, got {:?}", path, i + 1, l);
+            let (key, value) = l.split_once('=').ok_or_else(err)?;
+            let value: u64 = value.trim().parse().map_err(|_| err())?;
+            match key.trim() {
+                "cycle" => model.cycle = value as usize,
+                "newborn_delay" => model.newborn_delay = value as usize,
+                "offspring" => model.offspring = value,
+                "mortality" => model.mortality = Some(value as usize),
+                other => return Err(format!("{}:{}: unknown key {:?}", path, i + 1, other)),
+            }
+        }
+        if model.cycle == 0 {
+            return Err(format!("{}: cycle must be at least 1", path));
+        }
+        Ok(model)
+    }
+
+    // Without mortality, individuals are bucketed by days until they next
+    // spawn, like the puzzle's timers. With it, they're bucketed by age.
+    fn buckets(&self) -> usize {
+        match self.mortality {
+            None => self.cycle + self.newborn_delay,
+            Some(age) => age,
+        }
+    }
+
+    // Where an individual with the puzzle's timer value starts. In the age
+    // model timers are read as days until the next spawn, assuming they
+    // haven't spawned yet.
+    fn bucket_for_timer(&self, timer: usize) -> Option<usize> {
+        let first_spawn = self.cycle + self.newborn_delay;
+        if timer >= first_spawn {
+            return None;
+        }
+        match self.mortality {
+            None => Some(timer),
+            Some(age) => Some(first_spawn - 1 - timer).filter(|b| *b < age),
+        }
+    }
+
+    // One day as `(from, to, multiplier)` moves between buckets.
+    fn transitions(&self) -> Vec<(usize, usize, u64)> {
+        let first_spawn = self.cycle + self.newborn_delay;
+        match self.mortality {
+            None => {
+                let mut t: Vec<_> = (1..first_spawn).map(|b| (b, b - 1, 1)).collect();
+                t.push((0, self.cycle - 1, 1));
+                t.push((0, first_spawn - 1, self.offspring));
+                t
+            }
+            Some(age) => {
+                let mut t: Vec<_> = (0..age.saturating_sub(1)).map(|a| (a, a + 1, 1)).collect();
+                // Spawning happens on the day an individual turns
+                // `first_spawn`, `first_spawn + cycle` and so on.
+                t.extend(
+                    (0..age.saturating_sub(1))
+                        .filter(|a| {
+                            a + 1 >= first_spawn && (a + 1 - first_spawn).is_multiple_of(self.cycle)
+                        })
+                        .map(|a| (a, 0, self.offspring)),
+                );
+                t
+            }
+        }
+    }
+}
 
 fn main() {
+    // Part 1 is 80 days, part 2 is 256. Without arguments, do both.
+    let mut runs: Vec<u64> = vec![];
+    let mut trace = false;
+    let mut matrix = false;
+    let mut big = false;
+    let mut modulus: Option<BigUint> = None;
+    let mut model = Lifecycle::lanternfish();
+    let mut csv: Option<String> = None;
+    for arg in env::args().skip(1) {
+        match arg.as_str() {
+            "1" => runs.push(80),
+            "2" => runs.push(256),
+            "trace" => trace = true,
+            "matrix" => matrix = true,
+            "big" => big = true,
+            other => {
+                if let Some(days) = other.strip_prefix("days=").and_then(|n| n.parse().ok()) {
+                    runs.push(days);
+                } else if let Some(m) = other.strip_prefix("mod=").and_then(|n| n.parse().ok()) {
+                    modulus = Some(m);
+                    matrix = true;
+                } else if let Some(path) = other.strip_prefix("csv=") {
+                    csv = Some(path.to_string());
+                } else if let Some(path) = other.strip_prefix("model=") {
+                    model = Lifecycle::load(path).unwrap_or_else(|e| {
+                        eprintln!("{}", e);
+                        process::exit(1);
+                    });
+                } else {
+                    eprintln!(
+                        "unknown argument {:?}, expected 1, 2, days=<n>, trace, big, matrix, mod=<p>, model=<file> or csv=<file>",
+                        other
+                    );
+                    process::exit(1);
+                }
+            }
+        }
+    }
+    if modulus.as_ref().map(|m| m <= &BigUint::one()) == Some(true) {
+        eprintln!("modulus must be at least 2");
+        process::exit(1);
+    }
+    if runs.is_empty() {
+        runs = vec![80, 256];
+    }
+
     if let Ok(lines) = read_lines("./day6/input.txt") {
         let line = lines.last().unwrap().unwrap();
 
-        let fish: Vec<u8> = line.split(",").map(|x| x.parse().unwrap()).collect();
+        let fish: Vec<usize> = line.split(',').map(|x| x.parse().unwrap()).collect();
 
-        let mut fishies: Vec<u64> = vec![0; 9];
-        fish.iter().for_each(|x| {
-            fishies[*x as usize] += 1;
-        });
+        let mut fishies: Vec<u64> = vec![0; model.buckets()];
+        for x in fish {
+            match model.bucket_for_timer(x) {
+                Some(b) => fishies[b] += 1,
+                None => {
+                    eprintln!("timer {} doesn't fit the lifecycle {:?}", x, model);
+                    process::exit(1);
+                }
+            }
+        }
+
+        if let Some(path) = &csv {
+            let days = *runs.iter().max().unwrap();
+            let start: Vec<BigUint> = fishies.iter().map(|f| BigUint::from(*f)).collect();
+            if let Err(e) = write_series(&model, &start, days, path) {
+                eprintln!("{}: {}", path, e);
+                process::exit(1);
+            }
+            println!("wrote {} days to {}", days, path);
+            println!("asymptotic growth rate: {:.6} per day", growth_rate(&model));
+        }
+
+        for n in runs {
+            if matrix {
+                let start: Vec<BigUint> = fishies.iter().map(|f| BigUint::from(*f)).collect();
+                let after = simulate_matrix(&model, &start, n, modulus.as_ref());
+                println!("after {} days:", n);
+                println!("{:?}", after);
+                match &modulus {
+                    Some(m) => println!("{} (mod {})", after.iter().sum::<BigUint>() % m, m),
+                    None => println!("{}", after.iter().sum::<BigUint>()),
+                }
+                continue;
+            }
+
+            let result = if big {
+                let start: Vec<BigUint> = fishies.iter().map(|f| BigUint::from(*f)).collect();
+                report(n, simulate(&model, &start, n, trace))
+            } else {
+                report(n, simulate(&model, &fishies, n, trace))
+            };
+            if let Err(e) = result {
+                eprintln!("{}", e);
+                process::exit(1);
+            }
+        }
+    }
+}
+
+fn report<T>(n: u64, after: Result<Vec<T>, String>) -> Result<(), String>
+where
+    T: Clone + Zero + CheckedAdd + Debug + Display,
+{
+    let after = after?;
+    println!("after {} days:", n);
+    println!("{:?}", after);
+    println!("{}", total(&after).ok_or("total population overflowed")?);
+    Ok(())
+}
+
+// Fish are tracked by how many there are in each bucket rather than
+// individually. All arithmetic is checked, so this errors rather than
+// wrapping if the counts outgrow `T`.
+fn simulate<T>(model: &Lifecycle, start: &[T], n: u64, trace: bool) -> Result<Vec<T>, String>
+where
+    T: Clone + Zero + CheckedAdd + CheckedMul + From<u64> + Display,
+{
+    let transitions = model.transitions();
+    let mut fishies = start.to_vec();
+
+    for day in 1..=n {
+        let overflow = || format!("fish count overflowed on day {}", day);
+        let mut next = vec![T::zero(); fishies.len()];
+        for (from, to, multiplier) in &transitions {
+            let moved = fishies[*from]
+                .checked_mul(&T::from(*multiplier))
+                .ok_or_else(overflow)?;
+            next[*to] = next[*to].checked_add(&moved).ok_or_else(overflow)?;
+        }
+        fishies = next;
+
+        if trace {
+            let sum = total(&fishies)
+                .ok_or_else(|| format!("total population overflowed on day {}", day))?;
+            println!("day {}: {}", day, sum);
+        }
+    }
+
+    Ok(fishies)
+}
 
-        // Simulate each _type_ of fish and build a lookup table.
-        let n = 256;
+fn total<T: Clone + Zero + CheckedAdd>(fishies: &[T]) -> Option<T> {
+    fishies
+        .iter()
+        .try_fold(T::zero(), |acc, f| acc.checked_add(f))
+}
 
-        for _ in 0..n {
-            let old_fish = fishies.clone();
+// One row per day (including day 0) with every bucket, the total and the
+// ratio of the total to the previous day's.
+fn write_series(model: &Lifecycle, start: &[BigUint], days: u64, path: &str) -> io::Result<()> {
+    let mut out = BufWriter::new(File::create(path)?);
+    let buckets: Vec<String> = (0..start.len()).map(|b| format!("bucket{}", b)).collect();
+    writeln!(out, "day,{},total,growth", buckets.join(","))?;
 
-            fishies[8] = old_fish[0];
-            fishies[7] = old_fish[8];
-            fishies[6] = old_fish[7] + old_fish[0];
-            fishies[5] = old_fish[6];
-            fishies[4] = old_fish[5];
-            fishies[3] = old_fish[4];
-            fishies[2] = old_fish[3];
-            fishies[1] = old_fish[2];
-            fishies[0] = old_fish[1];
+    let mut fishies = start.to_vec();
+    let mut previous: Option<BigUint> = None;
+    for day in 0..=days {
+        if day > 0 {
+            // BigUint can't overflow, so this can't fail.
+            fishies = simulate(model, &fishies, 1, false).unwrap();
         }
+        let sum: BigUint = fishies.iter().sum();
+        let growth = match &previous {
+            Some(p) if !p.is_zero() => ratio(&sum, p).to_string(),
+            _ => String::new(),
+        };
+        let counts: Vec<String> = fishies.iter().map(|f| f.to_string()).collect();
+        writeln!(out, "{},{},{},{}", day, counts.join(","), sum, growth)?;
+        previous = Some(sum);
+    }
+    out.flush()
+}
 
-        println!("{:?}", fishies);
-        println!("{}", fishies.iter().sum::<u64>());
+// a / b as a float, without overflowing when both are huge.
+fn ratio(a: &BigUint, b: &BigUint) -> f64 {
+    let shift = b.bits().saturating_sub(64);
+    let (a, b) = (a >> shift, b >> shift);
+    a.to_f64().unwrap() / b.to_f64().unwrap()
+}
+
+// The dominant eigenvalue of the transition matrix, i.e. the factor the
+// population eventually grows by each day. Power iteration is done on
+// M + I, which has the same dominant eigenvector, so that periodic
+// lifecycles still converge. The estimate can hold still for a few steps
+// while the vector is still moving, so it's the vector that has to settle.
+fn growth_rate(model: &Lifecycle) -> f64 {
+    let size = model.buckets();
+    let transitions = model.transitions();
+    let mut v = vec![1.0 / size as f64; size];
+    let mut rate = 0.0;
+    for _ in 0..1_000_000 {
+        let mut next = v.clone();
+        for (from, to, multiplier) in &transitions {
+            next[*to] += v[*from] * *multiplier as f64;
+        }
+        let norm: f64 = next.iter().sum();
+        // `v` sums to 1, so the norm is the eigenvalue estimate.
+        rate = norm - 1.0;
+        let next: Vec<f64> = next.iter().map(|x| x / norm).collect();
+        let moved = next
+            .iter()
+            .zip(&v)
+            .map(|(a, b)| (a - b).abs())
+            .fold(0.0, f64::max);
+        v = next;
+        if moved < 1e-14 {
+            break;
+        }
     }
+    rate
+}
+
+type Matrix = Vec<Vec<BigUint>>;
+
+// One day as a linear map: `next[i] = sum(m[i][j] * today[j])`.
+fn transition_matrix(model: &Lifecycle) -> Matrix {
+    let size = model.buckets();
+    let mut m = vec![vec![BigUint::zero(); size]; size];
+    for (from, to, multiplier) in model.transitions() {
+        m[to][from] += BigUint::from(multiplier);
+    }
+    m
+}
+
+// Raises the transition matrix to the n-th power by repeated squaring, so
+// this is O(log n) matrix products. Without a modulus the result is exact,
+// but bear in mind the population has roughly 0.13 * n bits.
+fn simulate_matrix(
+    model: &Lifecycle,
+    start: &[BigUint],
+    n: u64,
+    modulus: Option<&BigUint>,
+) -> Vec<BigUint> {
+    let size = start.len();
+    let mut result: Matrix = (0..size)
+        .map(|i| {
+            (0..size)
+                .map(|j| {
+                    if i == j {
+                        BigUint::one()
+                    } else {
+                        BigUint::zero()
+                    }
+                })
+                .collect()
+        })
+        .collect();
+    let mut base = transition_matrix(model);
+    let mut n = n;
+    while n > 0 {
+        if n & 1 == 1 {
+            result = mat_mul(&result, &base, modulus);
+        }
+        base = mat_mul(&base, &base, modulus);
+        n >>= 1;
+    }
+
+    result
+        .iter()
+        .map(|row| {
+            let v = row.iter().zip(start).map(|(a, b)| a * b).sum::<BigUint>();
+            match modulus {
+                Some(m) => v % m,
+                None => v,
+            }
+        })
+        .collect()
+}
+
+fn mat_mul(a: &Matrix, b: &Matrix, modulus: Option<&BigUint>) -> Matrix {
+    let size = a.len();
+    (0..size)
+        .map(|i| {
+            (0..size)
+                .map(|j| {
+                    let v = (0..size).map(|k| &a[i][k] * &b[k][j]).sum::<BigUint>();
+                    match modulus {
+                        Some(m) => v % m,
+                        None => v,
+                    }
+                })
+                .collect()
+        })
+        .collect()
 }
 
 // The output is wrapped in a Result to allow matching on errors
@@ -44,3 +403,152 @@ where
     let file = File::open(filename)?;
     Ok(io::BufReader::new(file).lines())
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // The example's fish, bucketed for `model`.
+    fn example<T: Zero + Clone + From<u64>>(model: &Lifecycle) -> Vec<T> {
+        let mut fishies = vec![0; model.buckets()];
+        for timer in include_str!("example.txt").trim().split(',') {
+            let b = model.bucket_for_timer(timer.parse().unwrap()).unwrap();
+            fishies[b] += 1;
+        }
+        fishies.into_iter().map(T::from).collect()
+    }
+
+    fn population<T: Clone + Zero + CheckedAdd>(fishies: &[T]) -> T {
+        total(fishies).unwrap()
+    }
+
+    #[test]
+    fn example_populations() {
+        let model = Lifecycle::lanternfish();
+        let start: Vec<u64> = example(&model);
+        assert_eq!(
+            population(&simulate(&model, &start, 18, false).unwrap()),
+            26
+        );
+        assert_eq!(
+            population(&simulate(&model, &start, 80, false).unwrap()),
+            5934
+        );
+        assert_eq!(
+            population(&simulate(&model, &start, 256, false).unwrap()),
+            26984457539
+        );
+    }
+
+    #[test]
+    fn u64_counts_error_on_overflow_and_big_counts_are_exact() {
+        let model = Lifecycle::lanternfish();
+        let start: Vec<u64> = example(&model);
+        assert!(simulate(&model, &start, 511, false).is_ok());
+        assert_eq!(
+            simulate(&model, &start, 600, false),
+            Err("fish count overflowed on day 512".to_string())
+        );
+
+        let start: Vec<BigUint> = example(&model);
+        let after = simulate(&model, &start, 600, false).unwrap();
+        assert!(population(&after) > BigUint::from(u64::MAX));
+        // The population follows the same recurrence as each bucket
+        // (x^9 = x^2 + 1), so its exact value can be checked against the
+        // totals a week and nine days earlier.
+        let totals: Vec<BigUint> = [600, 593, 591]
+            .iter()
+            .map(|n| population(&simulate(&model, &start, *n, false).unwrap()))
+            .collect();
+        assert_eq!(totals[0], &totals[1] + &totals[2]);
+    }
+
+    #[test]
+    fn late_mortality_changes_nothing() {
+        let immortal = Lifecycle::lanternfish();
+        // Nobody in the example is older than 8, so nobody reaches 300
+        // within 256 days.
+        let mortal = Lifecycle {
+            mortality: Some(300),
+            ..Lifecycle::lanternfish()
+        };
+        let (a, b): (Vec<u64>, Vec<u64>) = (example(&immortal), example(&mortal));
+        for n in [0, 1, 18, 80, 256] {
+            assert_eq!(
+                population(&simulate(&immortal, &a, n, false).unwrap()),
+                population(&simulate(&mortal, &b, n, false).unwrap()),
+                "{} days",
+                n
+            );
+        }
+    }
+
+    #[test]
+    fn models_load_from_config() {
+        let path = concat!(env!("CARGO_MANIFEST_DIR"), "/day6/lifecycle.txt");
+        assert_eq!(
+            Lifecycle::load(path),
+            Ok(Lifecycle {
+                mortality: Some(37),
+                ..Lifecycle::lanternfish()
+            })
+        );
+    }
+
+    #[test]
+    fn growth_rates_are_dominant_eigenvalues() {
+        // The real root of x^9 = x^2 + 1.
+        let lanternfish = growth_rate(&Lifecycle::lanternfish());
+        assert!((lanternfish - 1.0910244).abs() < 1e-6, "{}", lanternfish);
+        assert!((lanternfish.powi(9) - lanternfish.powi(2) - 1.0).abs() < 1e-9);
+
+        // Everyone spawns in lockstep every third day, doubling each time.
+        let periodic = growth_rate(&Lifecycle {
+            cycle: 3,
+            newborn_delay: 3,
+            offspring: 2,
+            mortality: None,
+        });
+        assert!((periodic - 2f64.cbrt()).abs() < 1e-9, "{}", periodic);
+    }
+
+    #[test]
+    fn matrix_powers_match_day_by_day_simulation() {
+        let modulus = BigUint::from(1_000_000_007u64);
+        let models = [
+            Lifecycle::lanternfish(),
+            Lifecycle {
+                cycle: 3,
+                newborn_delay: 3,
+                offspring: 2,
+                mortality: None,
+            },
+            Lifecycle {
+                mortality: Some(20),
+                ..Lifecycle::lanternfish()
+            },
+        ];
+        for model in models {
+            let start: Vec<BigUint> = example(&model);
+            for n in [0, 1, 2, 7, 9, 18, 80, 256, 300] {
+                let expected = simulate(&model, &start, n, false).unwrap();
+                assert_eq!(
+                    simulate_matrix(&model, &start, n, None),
+                    expected,
+                    "{:?} {}",
+                    model,
+                    n
+                );
+                let reduced: Vec<BigUint> = expected.iter().map(|f| f % &modulus).collect();
+                assert_eq!(
+                    simulate_matrix(&model, &start, n, Some(&modulus)),
+                    reduced,
+                    "{:?} {} mod {}",
+                    model,
+                    n,
+                    modulus
+                );
+            }
+        }
+    }
+}
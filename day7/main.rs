@@ -1,38 +1,564 @@
+use std::env;
 use std::fs::File;
 use std::io::{self, BufRead};
 use std::path::Path;
+use std::process;
+
+#[derive(Debug)]
+struct Alignment {
+    // Every position achieving the minimum fuel, as inclusive runs in order.
+    positions: Vec<(i64, i64)>,
+    fuel: u64,
+}
+
+trait FuelCost {
+    fn name(&self) -> String;
+
+    // Fuel for a single crab to move `dist` steps, or None if it doesn't fit
+    // in a u64.
+    fn fuel(&self, dist: u64) -> Option<u64>;
+
+    // Fuel for the step from `dist` to `dist + 1`. Wide enough that it
+    // never overflows, so the slope of the total is always exact.
+    fn step(&self, dist: u64) -> u128;
+
+    // Whether the total fuel is convex in the target position, which holds
+    // whenever each extra step costs at least as much as the last.
+    fn is_convex(&self) -> bool;
+
+    // A direct solution, for costs that have one.
+    fn align(&self, _crabs: &[i64]) -> Option<Result<Alignment, String>> {
+        None
+    }
+}
+
+struct Linear;
+
+impl FuelCost for Linear {
+    fn name(&self) -> String {
+        "linear".to_string()
+    }
+
+    fn fuel(&self, dist: u64) -> Option<u64> {
+        Some(dist)
+    }
+
+    fn step(&self, _dist: u64) -> u128 {
+        1
+    }
+
+    fn is_convex(&self) -> bool {
+        true
+    }
+
+    // Every position between the lower and upper median minimises the
+    // total distance.
+    fn align(&self, crabs: &[i64]) -> Option<Result<Alignment, String>> {
+        let mut sorted = crabs.to_vec();
+        sorted.sort();
+        let (lo, hi) = (sorted[(sorted.len() - 1) / 2], sorted[sorted.len() / 2]);
+        Some(
+            total_fuel(crabs, lo, self)
+                .map(|fuel| Alignment {
+                    positions: vec![(lo, hi)],
+                    fuel,
+                })
+                .ok_or_else(|| overflow(self)),
+        )
+    }
+}
+
+struct Triangular;
+
+impl FuelCost for Triangular {
+    fn name(&self) -> String {
+        "triangular".to_string()
+    }
+
+    fn fuel(&self, dist: u64) -> Option<u64> {
+        // Triangular sequence. (n * (n + 1)) / 2, halving whichever factor is
+        // even first so the product only overflows if the result would.
+        if dist.is_multiple_of(2) {
+            (dist / 2).checked_mul(dist + 1)
+        } else {
+            dist.checked_mul(dist.div_ceil(2))
+        }
+    }
+
+    fn step(&self, dist: u64) -> u128 {
+        dist as u128 + 1
+    }
+
+    fn is_convex(&self) -> bool {
+        true
+    }
+
+    // The triangular cost is (d² + d) / 2, so the total is minimised within
+    // half a step of the mean; only the integers either side of it need
+    // checking, as long as they're in range.
+    fn align(&self, crabs: &[i64]) -> Option<Result<Alignment, String>> {
+        let sum: i128 = crabs.iter().map(|x| *x as i128).sum();
+        let mean = sum.div_euclid(crabs.len() as i128) as i64;
+        let min = *crabs.iter().min().unwrap();
+        let max = *crabs.iter().max().unwrap();
+        let candidates = mean.saturating_sub(1).max(min)..=mean.saturating_add(2).min(max);
+        Some(best_of(crabs, candidates, self))
+    }
+}
+
+struct Quadratic;
+
+impl FuelCost for Quadratic {
+    fn name(&self) -> String {
+        "quadratic".to_string()
+    }
+
+    fn fuel(&self, dist: u64) -> Option<u64> {
+        dist.checked_mul(dist)
+    }
+
+    fn step(&self, dist: u64) -> u128 {
+        2 * dist as u128 + 1
+    }
+
+    fn is_convex(&self) -> bool {
+        true
+    }
+}
+
+// Each step costs the rate of the last breakpoint at or below the distance
+// already travelled.
+struct Piecewise {
+    name: String,
+    // (distance, rate) sorted by distance, starting at 0.
+    rates: Vec<(u64, u64)>,
+}
+
+impl Piecewise {
+    // One `distance rate` pair per line, `#` starts a comment.
+    fn load(path: &str) -> Result<Piecewise, String> {
+        let lines = read_lines(path).map_err(|e| format!("{}: {}", path, e))?;
+        let mut rates = vec![];
+        for (i, l) in lines.map_while(Result::ok).enumerate() {
+            let l = l.split('#').next().unwrap().trim();
+            if l.is_empty() {
+                continue;
+            }
+            let pair: Vec<u64> = l
+                .split_whitespace()
+                .map(|n| n.parse().ok())
+                .collect::<Option<_>>()
+                .filter(|p: &Vec<u64>| p.len() == 2)
+                .ok_or_else(|| {
+                    format!("{}:{}: expected distance rate, got {:?}", path, i + 1, l)
+                })?;
+            rates.push((pair[0], pair[1]));
+        }
+        rates.sort();
+        if rates.first().map(|(d, _)| *d) != Some(0) {
+            return Err(format!("{}: needs a rate for distance 0", path));
+        }
+        Ok(Piecewise {
+            name: format!("piecewise {}", path),
+            rates,
+        })
+    }
+}
+
+impl FuelCost for Piecewise {
+    fn name(&self) -> String {
+        self.name.clone()
+    }
+
+    fn fuel(&self, dist: u64) -> Option<u64> {
+        self.rates
+            .iter()
+            .enumerate()
+            .try_fold(0u64, |acc, (i, (from, rate))| {
+                let to = self.rates.get(i + 1).map(|(d, _)| *d).unwrap_or(u64::MAX);
+                dist.min(to)
+                    .saturating_sub(*from)
+                    .checked_mul(*rate)
+                    .and_then(|f| acc.checked_add(f))
+            })
+    }
+
+    fn step(&self, dist: u64) -> u128 {
+        self.rates
+            .iter()
+            .rev()
+            .find(|(from, _)| *from <= dist)
+            .map(|(_, rate)| *rate as u128)
+            .unwrap_or(0)
+    }
+
+    fn is_convex(&self) -> bool {
+        self.rates.windows(2).all(|w| w[0].1 <= w[1].1)
+    }
+}
+
+#[derive(Debug, PartialEq)]
+enum Metric {
+    // Each axis is aligned on its own and the fuel summed, so with the
+    // linear cost this is the Manhattan distance. Other costs are reported
+    // as per-axis, since they aren't a function of the Manhattan distance.
+    Manhattan,
+    // Straight-line distance to a real-valued meeting point.
+    Euclidean,
+}
 
 fn main() {
-    if let Ok(lines) = read_lines("./day7/input.txt") {
+    let mut top = 5;
+    let mut costs: Vec<Box<dyn FuelCost>> = vec![];
+    let mut metric = Metric::Manhattan;
+    let mut input = "./day7/input.txt".to_string();
+    for arg in env::args().skip(1) {
+        if let Some(n) = arg.strip_prefix("top=").and_then(|n| n.parse().ok()) {
+            top = n;
+            continue;
+        }
+        if let Some(path) = arg.strip_prefix("input=") {
+            input = path.to_string();
+            continue;
+        }
+        match arg.as_str() {
+            "metric=manhattan" => {
+                metric = Metric::Manhattan;
+                continue;
+            }
+            "metric=euclidean" => {
+                metric = Metric::Euclidean;
+                continue;
+            }
+            _ => {}
+        }
+        match arg.strip_prefix("cost=") {
+            Some("linear") => costs.push(Box::new(Linear)),
+            Some("triangular") => costs.push(Box::new(Triangular)),
+            Some("quadratic") => costs.push(Box::new(Quadratic)),
+            Some(path) => match Piecewise::load(path) {
+                Ok(p) => costs.push(Box::new(p)),
+                Err(e) => {
+                    eprintln!("{}", e);
+                    process::exit(1);
+                }
+            },
+            None => {
+                eprintln!(
+                    "unknown argument {:?}, expected top=<n>, input=<file>, metric=<manhattan|euclidean> or cost=<linear|triangular|quadratic|file>",
+                    arg
+                );
+                process::exit(1);
+            }
+        }
+    }
+    if costs.is_empty() {
+        costs = vec![Box::new(Linear), Box::new(Triangular)];
+    }
+
+    if let Ok(lines) = read_lines(&input) {
         let line = lines.last().unwrap().unwrap();
-        let mut crabs: Vec<i32> = line.split(",").map(|x| x.parse().unwrap()).collect();
 
-        // Find the median and then move all crabs towards it?
-        crabs.sort();
+        // Crabs on a lattice are `x,y;x,y;...` (or `x,y;` for just one),
+        // otherwise it's the puzzle's plain list of positions.
+        let points = match parse_points(&line) {
+            Ok(points) => points,
+            Err(e) => {
+                eprintln!("{}", e);
+                process::exit(1);
+            }
+        };
+        if line.contains(';') || metric == Metric::Euclidean {
+            println!("{:?}", points);
+
+            if metric == Metric::Euclidean {
+                let (median, distance) = geometric_median(&points);
+                println!(
+                    "euclidean: aligning to {:?} covers {:.6} in total",
+                    median, distance
+                );
+                return;
+            }
+            for cost in &costs {
+                match align_axes(&points, cost.as_ref()) {
+                    Ok((target, fuel)) => println!(
+                        "{} per axis: aligning to {:?} costs {}",
+                        cost.name(),
+                        target,
+                        fuel
+                    ),
+                    Err(e) => eprintln!("{}", e),
+                }
+            }
+            return;
+        }
+
+        let crabs: Vec<i64> = points.into_iter().map(|p| p[0]).collect();
         println!("{:?}", crabs);
-        // let median = crabs[crabs.len() / 2];
-        // println!("Aligning to {}", median);
 
-        // Lol brute force?
-        let min = *crabs.iter().min().unwrap();
-        let max = *crabs.iter().max().unwrap();
+        for cost in &costs {
+            match optimise(&crabs, cost.as_ref()) {
+                Ok(alignment) => report(&crabs, &alignment, cost.as_ref(), top),
+                Err(e) => eprintln!("{}", e),
+            }
+        }
+    }
+}
 
-        let v: Vec<_> = (min..max)
-            .map(|cand| {
-                crabs.iter().fold(0, |acc, x| {
-                    // Triangular sequence. (n * (n + 1)) / 2
-                    let dist = (x - cand).abs();
-                    let cost = (dist * (dist + 1)) / 2;
-                    cost + acc
-                })
+// Points are separated by `;` and coordinates by `,`. A line without `;` is
+// read as one-dimensional crabs, so a single point on a lattice needs a
+// trailing `;`, as in `3,4;`.
+fn parse_points(line: &str) -> Result<Vec<Vec<i64>>, String> {
+    let line = line.trim();
+    let points: Vec<Vec<i64>> = if line.contains(';') {
+        let line = line.strip_suffix(';').unwrap_or(line);
+        line.split(';')
+            .map(|p| {
+                p.split(',')
+                    .map(|x| {
+                        x.trim()
+                            .parse()
+                            .map_err(|_| format!("bad coordinate {:?}", x))
+                    })
+                    .collect()
+            })
+            .collect::<Result<_, _>>()?
+    } else {
+        line.split(',')
+            .map(|x| {
+                x.trim()
+                    .parse()
+                    .map(|x| vec![x])
+                    .map_err(|_| format!("bad position {:?}", x))
             })
-            .collect();
+            .collect::<Result<_, _>>()?
+    };
+
+    let dims = points.first().map(|p| p.len()).ok_or("no crabs")?;
+    if let Some(i) = points.iter().position(|p| p.len() != dims) {
+        return Err(format!(
+            "crab {} has {} coordinates, expected {}",
+            i,
+            points[i].len(),
+            dims
+        ));
+    }
+    Ok(points)
+}
+
+// The fuel is the sum of each axis' fuel, so every axis can be optimised on
+// its own. Returns the first optimal position on each axis.
+fn align_axes(points: &[Vec<i64>], cost: &dyn FuelCost) -> Result<(Vec<i64>, u64), String> {
+    let mut target = vec![];
+    let mut fuel = 0u64;
+    for axis in 0..points[0].len() {
+        let crabs: Vec<i64> = points.iter().map(|p| p[axis]).collect();
+        let alignment = optimise(&crabs, cost)?;
+        target.push(alignment.positions[0].0);
+        fuel = fuel
+            .checked_add(alignment.fuel)
+            .ok_or_else(|| overflow(cost))?;
+    }
+    Ok((target, fuel))
+}
+
+// Weiszfeld's algorithm: repeatedly move to the average of the points,
+// weighted by the inverse of their distance from the current estimate.
+// Starts from the centroid. Returns the median and total distance to it.
+//
+// When the estimate lands on data points, Vardi and Zhang's modification
+// decides whether to stay: the points there hold it in place unless the
+// others pull harder than their number.
+fn geometric_median(points: &[Vec<i64>]) -> (Vec<f64>, f64) {
+    let dims = points[0].len();
+    let points: Vec<Vec<f64>> = points
+        .iter()
+        .map(|p| p.iter().map(|x| *x as f64).collect())
+        .collect();
+    let distance = |a: &[f64], b: &[f64]| -> f64 {
+        a.iter()
+            .zip(b)
+            .map(|(x, y)| (x - y).powi(2))
+            .sum::<f64>()
+            .sqrt()
+    };
+
+    let mut estimate: Vec<f64> = (0..dims)
+        .map(|d| points.iter().map(|p| p[d]).sum::<f64>() / points.len() as f64)
+        .collect();
+    for _ in 0..10_000 {
+        let mut next = vec![0.0; dims];
+        let mut weights = 0.0;
+        // How many points the estimate sits on, and the pull of the rest.
+        let mut landed = 0.0;
+        let mut pull = vec![0.0; dims];
+        for p in &points {
+            let dist = distance(p, &estimate);
+            // Points under the estimate would divide by zero.
+            if dist < 1e-12 {
+                landed += 1.0;
+                continue;
+            }
+            for d in 0..dims {
+                next[d] += p[d] / dist;
+                pull[d] += (p[d] - estimate[d]) / dist;
+            }
+            weights += 1.0 / dist;
+        }
+        if weights == 0.0 {
+            break;
+        }
+        next.iter_mut().for_each(|x| *x /= weights);
+        if landed > 0.0 {
+            let pull = pull.iter().map(|x| x * x).sum::<f64>().sqrt();
+            if pull <= landed {
+                break;
+            }
+            let stay = landed / pull;
+            for d in 0..dims {
+                next[d] = (1.0 - stay) * next[d] + stay * estimate[d];
+            }
+        }
+        let moved = distance(&next, &estimate);
+        estimate = next;
+        if moved < 1e-9 {
+            break;
+        }
+    }
+
+    let total = points.iter().map(|p| distance(p, &estimate)).sum();
+    (estimate, total)
+}
+
+fn report(crabs: &[i64], alignment: &Alignment, cost: &dyn FuelCost, top: usize) {
+    let positions: Vec<String> = alignment
+        .positions
+        .iter()
+        .map(|(first, last)| {
+            if first == last {
+                first.to_string()
+            } else {
+                format!("{}..={}", first, last)
+            }
+        })
+        .collect();
+    println!(
+        "{}: aligning to [{}] costs {}",
+        cost.name(),
+        positions.join(", "),
+        alignment.fuel
+    );
+
+    // Breakdown for the first optimal position. No single crab can overflow
+    // if the total didn't.
+    let target = alignment.positions[0].0;
+    let mut costs: Vec<(usize, i64, u64)> = crabs
+        .iter()
+        .enumerate()
+        .map(|(i, x)| (i, *x, cost.fuel(x.abs_diff(target)).unwrap()))
+        .collect();
+    costs.sort_by_key(|(_, _, cost)| std::cmp::Reverse(*cost));
+    for (i, x, cost) in costs.iter().take(top) {
+        println!(
+            "  crab {} at {} moves {} for {}",
+            i,
+            x,
+            x.abs_diff(target),
+            cost
+        );
+    }
+}
+
+// None if the total doesn't fit in a u64.
+fn total_fuel(crabs: &[i64], cand: i64, cost: &dyn FuelCost) -> Option<u64> {
+    crabs.iter().try_fold(0u64, |acc, x| {
+        cost.fuel(x.abs_diff(cand)).and_then(|f| acc.checked_add(f))
+    })
+}
+
+// How much the total changes moving from `cand` to `cand + 1`: the crabs at
+// or below `cand` get one step further away and the rest one step closer.
+// Exact even where the totals themselves overflow.
+fn slope(crabs: &[i64], cand: i64, cost: &dyn FuelCost) -> i128 {
+    crabs
+        .iter()
+        .map(|x| {
+            if *x <= cand {
+                cost.step(x.abs_diff(cand)) as i128
+            } else {
+                -(cost.step(x.abs_diff(cand) - 1) as i128)
+            }
+        })
+        .sum()
+}
+
+// The first position in `lo..=hi` where the slope satisfies `pred`, or `hi`
+// if none does. `pred` must hold on a suffix of the range.
+fn first_where(mut lo: i64, mut hi: i64, pred: impl Fn(i64) -> bool) -> i64 {
+    while lo < hi {
+        // The range can be wider than an i64.
+        let mid = ((lo as i128 + hi as i128).div_euclid(2)) as i64;
+        if pred(mid) {
+            hi = mid;
+        } else {
+            lo = mid + 1;
+        }
+    }
+    lo
+}
+
+fn overflow(cost: &dyn FuelCost) -> String {
+    format!("{}: fuel for every position overflows a u64", cost.name())
+}
+
+// Uses the cost's own solution if it has one, otherwise a binary search on
+// the slope when the total is convex and an exhaustive scan when it isn't.
+fn optimise(crabs: &[i64], cost: &dyn FuelCost) -> Result<Alignment, String> {
+    if let Some(alignment) = cost.align(crabs) {
+        return alignment;
+    }
 
-        let res = v.iter().min().unwrap();
+    let min = *crabs.iter().min().unwrap();
+    let max = *crabs.iter().max().unwrap();
+    if !cost.is_convex() {
+        return best_of(crabs, min..=max, cost);
+    }
+
+    // A convex total stops falling where the slope turns non-negative, and
+    // stays flat until it turns positive; that run is every minimiser. The
+    // slope is compared rather than the totals, which can overflow on both
+    // sides of a minimum that fits.
+    let first = first_where(min, max, |cand| slope(crabs, cand, cost) >= 0);
+    let last = first_where(first, max, |cand| slope(crabs, cand, cost) > 0);
+    let fuel = total_fuel(crabs, first, cost).ok_or_else(|| overflow(cost))?;
+    Ok(Alignment {
+        positions: vec![(first, last)],
+        fuel,
+    })
+}
 
-        // let res: i32 = crabs.iter().map(|x| (x - median).abs()).sum();
-        println!("{}", res);
+// Evaluates every candidate and keeps all the cheapest.
+fn best_of(
+    crabs: &[i64],
+    candidates: impl Iterator<Item = i64>,
+    cost: &dyn FuelCost,
+) -> Result<Alignment, String> {
+    let costs: Vec<_> = candidates
+        .filter_map(|cand| total_fuel(crabs, cand, cost).map(|fuel| (cand, fuel)))
+        .collect();
+    let fuel = costs
+        .iter()
+        .map(|(_, fuel)| *fuel)
+        .min()
+        .ok_or_else(|| overflow(cost))?;
+    let mut positions: Vec<(i64, i64)> = vec![];
+    for (cand, _) in costs.iter().filter(|(_, f)| *f == fuel) {
+        match positions.last_mut() {
+            Some((_, last)) if *last + 1 == *cand => *last = *cand,
+            _ => positions.push((*cand, *cand)),
+        }
     }
+    Ok(Alignment { positions, fuel })
 }
 
 // The output is wrapped in a Result to allow matching on errors
@@ -44,3 +570,207 @@ where
     let file = File::open(filename)?;
     Ok(io::BufReader::new(file).lines())
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rand::rngs::StdRng;
+    use rand::{Rng, SeedableRng};
+
+    // The cheapest total over every position in range, or None if they all
+    // overflow.
+    fn brute_force(crabs: &[i64], cost: &dyn FuelCost) -> Option<u64> {
+        let min = *crabs.iter().min().unwrap();
+        let max = *crabs.iter().max().unwrap();
+        (min..=max)
+            .filter_map(|cand| total_fuel(crabs, cand, cost))
+            .min()
+    }
+
+    fn assert_optimum(crabs: &[i64], cost: &dyn FuelCost, positions: (i64, i64), fuel: u64) {
+        let alignment = optimise(crabs, cost).unwrap();
+        assert_eq!(alignment.fuel, fuel, "{} fuel for {:?}", cost.name(), crabs);
+        assert_eq!(
+            alignment.positions,
+            vec![positions],
+            "{} positions for {:?}",
+            cost.name(),
+            crabs
+        );
+    }
+
+    // Random crabs, with random piecewise costs that may or may not be convex.
+    #[test]
+    fn optimiser_matches_brute_force() {
+        let mut rng = StdRng::seed_from_u64(7);
+        for _ in 0..300 {
+            let n = rng.gen_range(2..50);
+            let crabs: Vec<i64> = (0..n).map(|_| rng.gen_range(0..1000)).collect();
+
+            let mut rates = vec![(0, rng.gen_range(0..5))];
+            for _ in 0..rng.gen_range(0..4) {
+                rates.push((rng.gen_range(1..500), rng.gen_range(0..20)));
+            }
+            rates.sort();
+            rates.dedup_by_key(|(d, _)| *d);
+            let piecewise = Piecewise {
+                name: "random".to_string(),
+                rates,
+            };
+
+            let costs: [&dyn FuelCost; 4] = [&Linear, &Triangular, &Quadratic, &piecewise];
+            for cost in costs {
+                assert_eq!(
+                    Some(optimise(&crabs, cost).unwrap().fuel),
+                    brute_force(&crabs, cost),
+                    "{} disagrees for {:?} with {:?}",
+                    cost.name(),
+                    crabs,
+                    piecewise.rates
+                );
+            }
+        }
+    }
+
+    // Optima at the top of the range, which the old exclusive search missed.
+    #[test]
+    fn optimum_at_the_edge_of_the_range() {
+        assert_optimum(&[0, 5, 5], &Linear, (5, 5), 5);
+        assert_optimum(&[0, 5, 5], &Quadratic, (3, 3), 17);
+        assert_optimum(&[7, 7, 7], &Triangular, (7, 7), 0);
+        assert_eq!(brute_force(&[0, 5, 5], &Linear), Some(5));
+    }
+
+    // Spreads wide enough to overflow i32, up to ranges too wide for an i64.
+    #[test]
+    fn wide_spreads() {
+        assert_optimum(&[0, 100_000], &Triangular, (50_000, 50_000), 2_500_050_000);
+        assert_optimum(
+            &[0, 40_000, 80_000, 90_000],
+            &Quadratic,
+            (52_500, 52_500),
+            5_075_000_000,
+        );
+        assert_eq!(
+            brute_force(&[0, 40_000, 80_000, 90_000], &Quadratic),
+            Some(5_075_000_000)
+        );
+        assert_optimum(
+            &[-3_000_000_000, 3_000_000_000],
+            &Linear,
+            (-3_000_000_000, 3_000_000_000),
+            6_000_000_000,
+        );
+        assert_optimum(
+            &[0, 2_000_000_000, 4_000_000_000],
+            &Triangular,
+            (2_000_000_000, 2_000_000_000),
+            4_000_000_002_000_000_000,
+        );
+
+        // Crabs at the ends of the i64 range, where the range itself and the
+        // positions either side of the mean don't fit.
+        let steady = Piecewise {
+            name: "steady".to_string(),
+            rates: vec![(0, 1)],
+        };
+        assert_optimum(
+            &[i64::MIN, i64::MAX],
+            &steady,
+            (i64::MIN, i64::MAX),
+            u64::MAX,
+        );
+        let free = Piecewise {
+            name: "free".to_string(),
+            rates: vec![(0, 0)],
+        };
+        let spread = [-5_000_000_000_000_000_000, 5_000_000_000_000_000_000];
+        assert_optimum(&spread, &free, (spread[0], spread[1]), 0);
+        assert!(optimise(&spread, &Quadratic).is_err());
+        assert_optimum(&[i64::MAX, i64::MAX], &Triangular, (i64::MAX, i64::MAX), 0);
+        assert_optimum(
+            &[i64::MAX - 1, i64::MAX],
+            &Triangular,
+            (i64::MAX - 1, i64::MAX),
+            1,
+        );
+        assert_optimum(
+            &[i64::MIN, i64::MIN + 1],
+            &Triangular,
+            (i64::MIN, i64::MIN + 1),
+            1,
+        );
+    }
+
+    // Every position but the optimum's neighbourhood overflows a u64 total.
+    #[test]
+    fn overflowing_totals_around_the_optimum() {
+        let mut crabs = vec![0];
+        crabs.extend([4_000_000_000; 1000]);
+        assert_optimum(
+            &crabs,
+            &Quadratic,
+            (3_996_003_996, 3_996_003_996),
+            15_984_015_984_015_984_016,
+        );
+    }
+
+    #[test]
+    fn lattice_points() {
+        assert_eq!(parse_points("3,4").unwrap(), vec![vec![3], vec![4]]);
+        assert_eq!(parse_points("3,4;").unwrap(), vec![vec![3, 4]]);
+        assert_eq!(
+            parse_points("1,2;3,4;").unwrap(),
+            vec![vec![1, 2], vec![3, 4]]
+        );
+        assert!(parse_points("1,2;3").is_err());
+        assert!(parse_points("1,2;;").is_err());
+        assert_eq!(align_axes(&[vec![3, 4]], &Linear), Ok((vec![3, 4], 0)));
+    }
+
+    fn assert_median(points: &[Vec<i64>], median: &[f64], total: f64) {
+        let (found, distance) = geometric_median(points);
+        let error = found
+            .iter()
+            .zip(median)
+            .map(|(a, b)| (a - b).abs())
+            .fold(0.0, f64::max);
+        assert!(error < 1e-6, "{:?} for {:?}", found, points);
+        assert!(
+            (distance - total).abs() < 1e-6,
+            "{} for {:?}",
+            distance,
+            points
+        );
+    }
+
+    #[test]
+    fn geometric_medians() {
+        // Collinear points have the one-dimensional median, which is also
+        // a data point the estimate has to land on and stay at.
+        let collinear = [vec![0, 0], vec![1, 0], vec![2, 0], vec![7, 0], vec![20, 0]];
+        assert_median(&collinear, &[2.0, 0.0], 26.0);
+        let diagonal = [vec![0, 0], vec![1, 1], vec![2, 2], vec![7, 7], vec![20, 20]];
+        assert_median(&diagonal, &[2.0, 2.0], 26.0 * 2f64.sqrt());
+
+        // A point holding half the crabs or more is the median.
+        let majority = [vec![3, 3], vec![3, 3], vec![3, 3], vec![0, 0], vec![10, 0]];
+        let total = 18f64.sqrt() + 58f64.sqrt();
+        assert_median(&majority, &[3.0, 3.0], total);
+
+        // Starting on the median, which is the centroid here.
+        let square = [vec![0, 0], vec![2, 0], vec![0, 2], vec![2, 2], vec![1, 1]];
+        assert_median(&square, &[1.0, 1.0], 4.0 * 2f64.sqrt());
+
+        // The Fermat point of a triangle with no angle over 120°.
+        let triangle = [vec![0, 0], vec![2, 0], vec![1, 2]];
+        let (median, _) = geometric_median(&triangle);
+        assert!((median[0] - 1.0).abs() < 1e-6, "{:?}", median);
+        assert!((median[1] - 1.0 / 3f64.sqrt()).abs() < 1e-6, "{:?}", median);
+    }
+
+    #[test]
+    fn overflow_everywhere_is_an_error() {
+        assert!(optimise(&[0, 10_000_000_000], &Quadratic).is_err());
+    }
+}